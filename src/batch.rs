@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use futures_util::stream::{self, Stream, StreamExt};
+
+use crate::source::RangeSource;
+use crate::{Error, Hash, hash, Password, Prefix, Result, scan_range, Suffix};
+
+/// the number of ranges fetched at the same time by default
+const DEFAULT_CONCURRENCY: usize = 8;
+
+/// A batch of password lookups,
+/// for checking many passwords against the [online API](crate::Api)
+/// or any other [`RangeSource`].
+///
+/// The passwords are grouped by their hash prefix,
+/// so each range is fetched only once,
+/// and a limited number of ranges is fetched at the same time.
+/// A failed range only fails the lookups of that range.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(feature = "reqwest")] {
+/// # tokio_test::block_on(async {
+/// use passleak::{Api, Batch};
+///
+/// let api = Api::new();
+/// let mut batch = Batch::new(&api).concurrency(4);
+/// for password in ["secret", "P@ssw0rd", "correct horse battery staple"] {
+///     batch.push(password);
+/// }
+/// for count in batch.run().await {
+///     println!("{:?}", count);
+/// }
+/// # })
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Batch<'a, S: ?Sized> {
+    source: &'a S,
+    hashes: Vec<Hash>,
+    concurrency: usize,
+}
+
+impl<'a, S: RangeSource + ?Sized> Batch<'a, S> {
+    /// Create an empty batch.
+    ///
+    /// This fetches 8 ranges at the same time by default.
+    pub fn new(source: &'a S) -> Self {
+        Self {
+            source,
+            hashes: Vec::new(),
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    /// Set the maximum number of ranges fetched at the same time.
+    ///
+    /// A concurrency of `0` is treated as `1`.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Add a password to the batch.
+    ///
+    /// Only the hash of the password is kept.
+    /// Returns the index of the password in the results.
+    pub fn push<P: Password + ?Sized>(&mut self, password: &P) -> usize {
+        self.push_hash(Hash::from(hash(password)))
+    }
+
+    /// Add a SHA-1 password hash to the batch.
+    ///
+    /// Returns the index of the hash in the results.
+    pub fn push_hash(&mut self, hash: Hash) -> usize {
+        self.hashes.push(hash);
+        self.hashes.len() - 1
+    }
+
+    /// Get the number of lookups in the batch.
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Check if the batch has no lookups.
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Count the number of known breaches of every lookup,
+    /// in the order they were added to the batch.
+    ///
+    /// Every entry of a range is compared in constant time.
+    pub async fn run(self) -> Vec<Result<u32>> {
+        let mut results = (0..self.len()).map(|_| None).collect::<Vec<_>>();
+        let mut stream = std::pin::pin!(self.into_stream());
+        while let Some((index, result)) = stream.next().await {
+            results[index] = Some(result);
+        }
+        results.into_iter()
+            .map(|result| result.expect("every lookup has a result"))
+            .collect()
+    }
+
+    /// Count the number of known breaches of every lookup,
+    /// in the order the ranges are fetched.
+    ///
+    /// Each item has the index of the lookup,
+    /// as returned when it was added to the batch.
+    /// Every entry of a range is compared in constant time.
+    pub fn into_stream(self) -> impl Stream<Item=(usize, Result<u32>)> + 'a {
+        let source = self.source;
+        let mut groups = BTreeMap::<Prefix, Vec<(usize, Suffix)>>::new();
+        for (index, hash) in self.hashes.into_iter().enumerate() {
+            let (prefix, suffix) = hash.split();
+            groups.entry(prefix).or_default().push((index, suffix));
+        }
+        stream::iter(groups)
+            .map(move |(prefix, lookups)| async move {
+                let range = source.fetch_range(prefix).await;
+                count_lookups(range, lookups)
+            })
+            .buffer_unordered(self.concurrency)
+            .flat_map(stream::iter)
+    }
+}
+
+/// Count the number of known breaches of the lookups of a range.
+///
+/// If the range could not be fetched, every lookup gets the error
+/// as an [`Error::Shared`], also when there is a single lookup.
+fn count_lookups(
+    range: Result<Vec<(Suffix, u32)>>,
+    lookups: Vec<(usize, Suffix)>,
+) -> Vec<(usize, Result<u32>)> {
+    match range {
+        Ok(range) => lookups.into_iter()
+            .map(|(index, suffix)| {
+                let count = scan_range(&suffix, range.iter().cloned(), true);
+                (index, Ok(count.unwrap_or(0)))
+            })
+            .collect(),
+        Err(error) => {
+            let error = match error {
+                Error::Shared(error) => error,
+                error => Arc::new(error),
+            };
+            lookups.into_iter()
+                .map(|(index, _suffix)| (index, Err(Error::Shared(error.clone()))))
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use futures_util::StreamExt;
+
+    use super::Batch;
+    use crate::source::RangeSource;
+    use crate::{Api, Error, Prefix, Result, Suffix};
+    use crate::test_support::{breached_corpus, Unreachable};
+
+    /// A source that fails every fetch.
+    #[derive(Debug)]
+    struct Failing;
+
+    #[async_trait]
+    impl RangeSource for Failing {
+        async fn fetch_range(&self, _prefix: Prefix) -> Result<Vec<(Suffix, u32)>> {
+            Err(Error::MalformedResponse)
+        }
+    }
+
+    #[test]
+    fn test_batch() {
+        let corpus = breached_corpus();
+        let passwords = ["secret", "P@ssw0rd", "password", "P@ssw0rd"];
+        let mut batch = Batch::new(&corpus).concurrency(2);
+        for password in passwords {
+            batch.push(password);
+        }
+        assert_eq!(batch.len(), 4);
+        tokio_test::block_on(async {
+            let counts = batch.run().await
+                .into_iter()
+                .map(|result| result.unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(counts, [0, 52579, 0, 52579]);
+
+            let api = Api::builder().transport(Unreachable).build().unwrap();
+            let mut batch = Batch::new(&api);
+            for password in passwords {
+                batch.push(password);
+            }
+            let mut results = batch.into_stream().collect::<Vec<_>>().await;
+            results.sort_by_key(|(index, _result)| *index);
+            assert_eq!(results.len(), 4);
+            for (_index, result) in &results {
+                let error = result.as_ref().unwrap_err();
+                assert!(matches!(error, Error::Shared(_)));
+                assert!(matches!(error.inner(), Error::Transport(_)));
+            }
+
+            // a single lookup of a range gets a shared error too
+            let mut batch = Batch::new(&Failing);
+            batch.push("P@ssw0rd");
+            let results = batch.run().await;
+            assert!(matches!(&results[..], [Err(Error::Shared(_))]));
+            assert!(matches!(results[0].as_ref().unwrap_err().inner(), Error::MalformedResponse));
+        });
+    }
+}
@@ -0,0 +1,321 @@
+//! A synchronous interface to the API.
+//!
+//! This mirrors the async [`Api`](crate::Api),
+//! using the blocking [`reqwest::blocking::Client`]
+//! instead of an async runtime.
+//! Hashing and parsing are shared, so the results are identical.
+//!
+//! This requires the `blocking` cargo feature.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use passleak::blocking::Api;
+//!
+//! let api = Api::new();
+//! let breaches = api.count_breaches("secret").expect("api error");
+//! assert!(breaches > 0);
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::HeaderMap;
+use reqwest::Url;
+
+use crate::policy::FailOpenHook;
+use crate::validate::validate_range;
+use crate::{
+    ApiBuilder, BreachStatus, check_range, Error, FailurePolicy, Hash, hash, Mode, NtlmHash,
+    NtlmSuffix, Password, Prefix, RangeDiagnostics, RangeIter, range_url, Result, RetryPolicy,
+    scan_range, status_error, Suffix,
+};
+
+/// The blocking API configuration.
+///
+/// Use [`Api::builder`] for anything but the default configuration,
+/// and create the instance with [`ApiBuilder::build_blocking`].
+///
+/// The blocking client runs its own runtime,
+/// so this must not be created or used within an async runtime.
+///
+/// Like the [async API](crate::Api), the errors of range requests
+/// are wrapped in [`Error::Shared`], see [`Error::inner`].
+#[derive(Clone, Debug)]
+pub struct Api {
+    pub(crate) client: Client,
+    pub(crate) base_url: Url,
+    pub(crate) headers: HeaderMap,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) add_padding: bool,
+    pub(crate) constant_time: bool,
+    pub(crate) strict_validation: bool,
+    pub(crate) failure_policy: FailurePolicy,
+    pub(crate) fail_open_hook: FailOpenHook,
+}
+
+impl Default for Api {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Api {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self::builder().build_blocking().unwrap()
+    }
+
+    /// Create a builder to configure a new instance.
+    ///
+    /// Use [`ApiBuilder::build_blocking`] to create the instance.
+    pub fn builder() -> ApiBuilder {
+        ApiBuilder::new()
+    }
+
+    /// Create a new instance with a custom [`reqwest::blocking::Client`].
+    pub fn with_client(client: Client) -> Self {
+        Self::builder().blocking_client(client).build_blocking().unwrap()
+    }
+
+    /// Create a new instance that queries a different service,
+    /// such as a mirror or a local stand-in.
+    ///
+    /// See [`crate::Api::with_base_url`].
+    pub fn with_base_url(base_url: &str) -> Result<Self> {
+        Self::builder().base_url(base_url).build_blocking()
+    }
+
+    /// Create the API request for a password range.
+    ///
+    /// The timeout is limited to the time left until the retry deadline.
+    fn range_request(&self, prefix: Prefix, mode: Mode, left: Option<Duration>) -> RequestBuilder {
+        let mut request = self.client.get(range_url(&self.base_url, prefix, mode))
+            .headers(self.headers.clone());
+        let timeout = match (self.timeout, left) {
+            (Some(timeout), Some(left)) => Some(timeout.min(left)),
+            (timeout, left) => timeout.or(left),
+        };
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        if self.add_padding {
+            request = request.header("Add-Padding", "true");
+        }
+        request
+    }
+
+    /// Get the API response for a password range.
+    ///
+    /// Responses with a non-success status code are turned into errors.
+    fn range_response(
+        &self,
+        prefix: Prefix,
+        mode: Mode,
+        left: Option<Duration>,
+    ) -> Result<Response> {
+        let response = self.range_request(prefix, mode, left).send()?;
+        match status_error(response.status().as_u16(), response.headers()) {
+            Some(error) => Err(error),
+            None => Ok(response),
+        }
+    }
+
+    /// Get the API response body bytes for a password range,
+    /// and retry failed requests.
+    ///
+    /// Errors are wrapped in [`Error::Shared`], like those of the async API.
+    fn range_body(&self, prefix: Prefix, mode: Mode) -> Result<Bytes> {
+        let body = self.retry.run_blocking(|left| {
+            let response = self.range_response(prefix, mode, left)?;
+            Ok(response.bytes()?)
+        });
+        let body = body.and_then(|body| {
+            if self.strict_validation {
+                check_range(&body, mode, self.add_padding)?;
+            }
+            Ok(body)
+        });
+        body.map_err(|error| Error::Shared(Arc::new(error)))
+    }
+
+    /// Get the API response for a password range,
+    /// and check that it is well-formed.
+    ///
+    /// See [`crate::Api::validate_range`].
+    pub fn validate_range(&self, prefix: Prefix) -> Result<RangeDiagnostics> {
+        let body = self.retry.run_blocking(|left| {
+            let response = self.range_response(prefix, Mode::Sha1, left)?;
+            Ok(response.bytes()?)
+        })?;
+        Ok(validate_range(&body, Mode::Sha1, self.add_padding))
+    }
+
+    /// Get the API response body bytes for a password range.
+    ///
+    /// Use this method if you want to parse the response body yourself.
+    pub fn range_bytes(&self, prefix: Prefix) -> Result<Bytes> {
+        self.range_body(prefix, Mode::Sha1)
+    }
+
+    /// Get the API response body text for a password range.
+    ///
+    /// Use this method if you want to parse the response body yourself.
+    /// Invalid UTF-8 sequences are replaced.
+    pub fn range_text(&self, prefix: Prefix) -> Result<String> {
+        let body = self.range_body(prefix, Mode::Sha1)?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+
+    /// Get the API response for a password range,
+    /// and parse it into an iterator of password hash suffixes and
+    /// corresponding breach counts.
+    ///
+    /// Lines that cannot be parsed yield `Err(Bytes)`.
+    pub fn range_raw(&self, prefix: Prefix) -> Result<impl Iterator<Item=Result<(Suffix, u32), Bytes>>> {
+        let body = self.range_bytes(prefix)?;
+        Ok(RangeIter::new(body))
+    }
+
+    /// Get the API response for a password range,
+    /// and parse it into an iterator of password hash suffixes and
+    /// corresponding breach counts.
+    ///
+    /// Lines that cannot be parsed are omitted.
+    pub fn range(&self, prefix: Prefix) -> Result<impl Iterator<Item=(Suffix, u32)>> {
+        let body = self.range_bytes(prefix)?;
+        Ok(RangeIter::new(body).filter_map(|result| result.ok()))
+    }
+
+    /// Count the number of known breaches for a password.
+    ///
+    /// See [`crate::Api::count_breaches`].
+    pub fn count_breaches<P: Password + ?Sized>(&self, password: &P) -> Result<u32> {
+        let hash = Hash::from(hash(password));
+        self.count_breaches_for_hash(&hash)
+    }
+
+    /// Check if there exist known breaches for a password.
+    ///
+    /// See [`crate::Api::is_breached`].
+    pub fn is_breached<P: Password + ?Sized>(&self, password: &P) -> Result<bool> {
+        let count = self.count_breaches(password)?;
+        Ok(count > 0)
+    }
+
+    /// Count the number of known breaches for a SHA-1 password hash.
+    ///
+    /// See [`crate::Api::count_breaches_for_hash`].
+    pub fn count_breaches_for_hash(&self, hash: &Hash) -> Result<u32> {
+        let range = self.range(hash.prefix())?;
+        scan_range(hash.suffix(), range, self.constant_time)
+            .ok_or(Error::MalformedResponse)
+    }
+
+    /// Check if there exist known breaches for a SHA-1 password hash.
+    ///
+    /// See [`crate::Api::is_hash_breached`].
+    pub fn is_hash_breached(&self, hash: &Hash) -> Result<bool> {
+        let count = self.count_breaches_for_hash(hash)?;
+        Ok(count > 0)
+    }
+
+    /// Check a password, with an explicit result
+    /// when it is not known whether it has been breached.
+    pub fn check<P: Password + ?Sized>(&self, password: &P) -> BreachStatus {
+        BreachStatus::from_count(self.count_breaches(password))
+    }
+
+    /// Check a SHA-1 password hash, with an explicit result
+    /// when it is not known whether it has been breached.
+    pub fn check_hash(&self, hash: &Hash) -> BreachStatus {
+        BreachStatus::from_count(self.count_breaches_for_hash(hash))
+    }
+
+    /// Check if a password may be used.
+    ///
+    /// See [`crate::Api::is_allowed`].
+    pub fn is_allowed<P: Password + ?Sized>(&self, password: &P) -> bool {
+        self.is_allowed_with(password, self.failure_policy)
+    }
+
+    /// Check if a password may be used.
+    ///
+    /// See [`crate::Api::is_allowed_with`].
+    pub fn is_allowed_with<P: Password + ?Sized>(
+        &self,
+        password: &P,
+        policy: FailurePolicy,
+    ) -> bool {
+        let status = self.check(password);
+        self.fail_open_hook.decide(&status, policy)
+    }
+
+    /// Get the API response body bytes for an NTLM password range.
+    ///
+    /// Use this method if you want to parse the response body yourself.
+    pub fn range_ntlm_bytes(&self, prefix: Prefix) -> Result<Bytes> {
+        self.range_body(prefix, Mode::Ntlm)
+    }
+
+    /// Get the API response for an NTLM password range,
+    /// and parse it into an iterator of password hash suffixes and
+    /// corresponding breach counts.
+    ///
+    /// Lines that cannot be parsed are omitted.
+    pub fn range_ntlm(&self, prefix: Prefix) -> Result<impl Iterator<Item=(NtlmSuffix, u32)>> {
+        let body = self.range_ntlm_bytes(prefix)?;
+        Ok(RangeIter::from_bytes(body).filter_map(|result| result.ok()))
+    }
+
+    /// Count the number of known breaches for an NTLM password hash.
+    ///
+    /// See [`crate::Api::count_breaches_for_ntlm_hash`].
+    pub fn count_breaches_for_ntlm_hash(&self, hash: &NtlmHash) -> Result<u32> {
+        let range = self.range_ntlm(hash.prefix())?;
+        scan_range(hash.suffix(), range, self.constant_time)
+            .ok_or(Error::MalformedResponse)
+    }
+
+    /// Check if there exist known breaches for an NTLM password hash.
+    ///
+    /// See [`crate::Api::is_ntlm_hash_breached`].
+    pub fn is_ntlm_hash_breached(&self, hash: &NtlmHash) -> Result<bool> {
+        let count = self.count_breaches_for_ntlm_hash(hash)?;
+        Ok(count > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Api;
+    use crate::test_support::range_server;
+    use crate::{ApiBuilder, Error};
+
+    #[test]
+    fn test_blocking() {
+        let (base_url, requests) = range_server(Duration::ZERO);
+        let api = Api::builder()
+            .base_url(base_url)
+            .user_agent("tests")
+            .build_blocking()
+            .unwrap();
+        assert_eq!(api.count_breaches("P@ssw0rd").unwrap(), 52579);
+        let request = requests.lock().unwrap()[0].to_lowercase();
+        assert!(request.starts_with("get /range/21bd1 "));
+        assert!(request.contains("user-agent: tests\r\n"));
+        assert!(request.contains("add-padding: true\r\n"));
+
+        let api = Api::with_base_url("http://127.0.0.1:1/").unwrap();
+        let error = api.is_breached("P@ssw0rd").unwrap_err();
+        assert!(matches!(error, Error::Shared(_)));
+        assert!(matches!(error.inner(), Error::Request(_)));
+        let client = reqwest::Client::new();
+        assert!(ApiBuilder::new().client(client).build_blocking().is_err());
+    }
+}
@@ -0,0 +1,342 @@
+use std::time::Duration;
+
+use std::sync::Arc;
+
+use http::header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+use url::Url;
+
+use crate::{Api, DEFAULT_BASE_URL, Error, FailurePolicy, Result, RetryPolicy};
+use crate::policy::FailOpenHook;
+use crate::flight::InFlight;
+use crate::transport::Transport;
+#[cfg(feature = "reqwest")]
+use crate::transport::ReqwestTransport;
+
+/// the user agent sent when none is configured
+const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// A builder for an [`Api`] instance.
+///
+/// All configuration is checked once by [`build`](Self::build),
+/// the resulting [`Api`] is immutable and can be shared
+/// between tasks, for example behind an [`Arc`](std::sync::Arc).
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "reqwest")] {
+/// use std::time::Duration;
+/// use passleak::Api;
+///
+/// let api = Api::builder()
+///     .user_agent("my-signup-service")
+///     .timeout(Duration::from_secs(5))
+///     .build()
+///     .expect("invalid configuration");
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ApiBuilder {
+    transport: Option<Arc<dyn Transport>>,
+    #[cfg(feature = "blocking")]
+    blocking_client: Option<reqwest::blocking::Client>,
+    base_url: String,
+    user_agent: String,
+    headers: Vec<(String, String)>,
+    timeout: Option<Duration>,
+    retry: RetryPolicy,
+    add_padding: bool,
+    constant_time: bool,
+    strict_validation: bool,
+    failure_policy: FailurePolicy,
+    fail_open_hook: FailOpenHook,
+}
+
+impl Default for ApiBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ApiBuilder {
+    /// Create a builder with the default configuration.
+    pub fn new() -> Self {
+        Self {
+            transport: None,
+            #[cfg(feature = "blocking")]
+            blocking_client: None,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            headers: Vec::new(),
+            timeout: None,
+            retry: RetryPolicy::none(),
+            add_padding: true,
+            constant_time: true,
+            strict_validation: false,
+            failure_policy: FailurePolicy::FailClosed,
+            fail_open_hook: FailOpenHook::default(),
+        }
+    }
+
+    /// Use a custom [`reqwest::Client`].
+    ///
+    /// The other options are applied to every request,
+    /// so they also work with a custom client.
+    #[cfg(feature = "reqwest")]
+    pub fn client(self, client: reqwest::Client) -> Self {
+        self.transport(ReqwestTransport::new(client))
+    }
+
+    /// Use a custom [`Transport`] to send requests,
+    /// such as a different HTTP client.
+    ///
+    /// This defaults to a [`ReqwestTransport`](crate::transport::ReqwestTransport)
+    /// with the `reqwest` cargo feature,
+    /// and is required without it.
+    pub fn transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Use a custom [`reqwest::blocking::Client`]
+    /// for a [`blocking::Api`](crate::blocking::Api).
+    ///
+    /// The other options are applied to every request,
+    /// so they also work with a custom client.
+    #[cfg(feature = "blocking")]
+    pub fn blocking_client(mut self, client: reqwest::blocking::Client) -> Self {
+        self.blocking_client = Some(client);
+        self
+    }
+
+    /// Set the base URL of the service,
+    /// such as a mirror or a local stand-in.
+    ///
+    /// Range requests go to `{base_url}range/{prefix}`,
+    /// the same as for the [default](DEFAULT_BASE_URL) service.
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Set the `User-Agent` header.
+    ///
+    /// The service asks for a user agent that describes the application.
+    /// This defaults to the name and version of this crate.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Add a header to every request.
+    pub fn header(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set a timeout for each request,
+    /// from sending it until the response body is received.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set when to retry failed requests.
+    ///
+    /// Requests are not retried by default.
+    /// The [timeout](Self::timeout) applies to each attempt,
+    /// see [`RetryPolicy::deadline`] to limit the total time.
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Set whether to enable padded responses.
+    ///
+    /// This is turned on (`true`) by default, which prevents leaking
+    /// the password hash prefix through the response size
+    /// by including a number of random hashes with zero breaches.
+    ///
+    /// Setting this to `false` reduces data usage.
+    ///
+    /// **Reference:**
+    /// <https://www.troyhunt.com/enhancing-pwned-passwords-privacy-with-padding/>
+    pub fn add_padding(mut self, add_padding: bool) -> Self {
+        self.add_padding = add_padding;
+        self
+    }
+
+    /// Set whether to compare every entry of a range response.
+    ///
+    /// This is turned on (`true`) by default, so the time taken
+    /// to find the breach count does not depend on
+    /// the position of the password in the range response.
+    ///
+    /// Setting this to `false` stops at the first match,
+    /// which is slightly faster.
+    /// Lookups then parse the response while it is received
+    /// and stop reading it at the match,
+    /// but no longer share requests with concurrent lookups.
+    pub fn constant_time(mut self, constant_time: bool) -> Self {
+        self.constant_time = constant_time;
+        self
+    }
+
+    /// Set whether to reject range responses that are not well-formed.
+    ///
+    /// This is turned off (`false`) by default,
+    /// which ignores lines that cannot be parsed.
+    ///
+    /// Setting this to `true` fails with [`Error::InvalidRange`]
+    /// if a suffix is not uppercase base16, the entries are not
+    /// sorted or unique, or padding was requested but no padding
+    /// entries are present, such as when a proxy changes the response.
+    /// Responses are then received completely before they are read.
+    ///
+    /// This applies to every method that gets a range,
+    /// including the responses stored by a
+    /// [`DiskCache`](crate::cache::DiskCache),
+    /// except [`Api::validate_range`] which reports the problems instead.
+    pub fn strict_validation(mut self, strict_validation: bool) -> Self {
+        self.strict_validation = strict_validation;
+        self
+    }
+
+    /// Set whether to allow passwords when the lookup fails,
+    /// for [`Api::is_allowed`].
+    ///
+    /// This is [`FailurePolicy::FailClosed`] by default.
+    pub fn failure_policy(mut self, failure_policy: FailurePolicy) -> Self {
+        self.failure_policy = failure_policy;
+        self
+    }
+
+    /// Set a function to call for every password
+    /// that is allowed because the lookup failed,
+    /// for example to log the error.
+    pub fn on_fail_open(mut self, hook: impl Fn(&Error) + Send + Sync + 'static) -> Self {
+        self.fail_open_hook = FailOpenHook::new(hook);
+        self
+    }
+
+    /// Create the [`Api`] instance.
+    ///
+    /// Fails with [`Error::Config`] if the base URL or
+    /// any of the headers is invalid,
+    /// or if no transport was set without the `reqwest` cargo feature.
+    pub fn build(self) -> Result<Api> {
+        let base_url = parse_base_url(&self.base_url)?;
+        let headers = self.parse_headers()?;
+        let transport = match self.transport {
+            Some(transport) => transport,
+            #[cfg(feature = "reqwest")]
+            None => Arc::new(ReqwestTransport::default()),
+            #[cfg(not(feature = "reqwest"))]
+            None => return Err(Error::Config("no transport was set".into())),
+        };
+        Ok(Api {
+            transport,
+            in_flight: InFlight::default(),
+            base_url,
+            headers,
+            timeout: self.timeout,
+            retry: self.retry,
+            add_padding: self.add_padding,
+            constant_time: self.constant_time,
+            strict_validation: self.strict_validation,
+            failure_policy: self.failure_policy,
+            fail_open_hook: self.fail_open_hook,
+        })
+    }
+
+    /// Create a [`blocking::Api`](crate::blocking::Api) instance.
+    ///
+    /// Fails with [`Error::Config`] if the base URL or
+    /// any of the headers is invalid,
+    /// or if a custom async [`transport`](Self::transport) was set.
+    #[cfg(feature = "blocking")]
+    pub fn build_blocking(self) -> Result<crate::blocking::Api> {
+        if self.transport.is_some() {
+            return Err(Error::Config("an async transport cannot be used for blocking requests".into()))
+        }
+        let base_url = parse_base_url(&self.base_url)?;
+        let headers = self.parse_headers()?;
+        Ok(crate::blocking::Api {
+            client: self.blocking_client.unwrap_or_default(),
+            base_url,
+            headers,
+            timeout: self.timeout,
+            retry: self.retry,
+            add_padding: self.add_padding,
+            constant_time: self.constant_time,
+            strict_validation: self.strict_validation,
+            failure_policy: self.failure_policy,
+            fail_open_hook: self.fail_open_hook,
+        })
+    }
+
+    /// Parse the headers to send with every request.
+    fn parse_headers(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, parse_header_value(&self.user_agent)?);
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| Error::Config(format!("invalid header name {:?}", name)))?;
+            headers.append(name, parse_header_value(value)?);
+        }
+        Ok(headers)
+    }
+}
+
+fn parse_header_value(value: &str) -> Result<HeaderValue> {
+    HeaderValue::from_str(value)
+        .map_err(|_| Error::Config(format!("invalid header value {:?}", value)))
+}
+
+/// Parse and validate the base URL of the API.
+///
+/// The path always ends with a slash so that range paths can be joined.
+fn parse_base_url(base_url: &str) -> Result<Url> {
+    let mut url = Url::parse(base_url)
+        .map_err(|error| Error::Config(format!("invalid base url: {}", error)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(Error::Config("base url must use http or https".into()))
+    }
+    if url.cannot_be_a_base()
+        || url.query().is_some()
+        || url.fragment().is_some()
+    {
+        return Err(Error::Config("base url must be a plain path".into()))
+    }
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiBuilder, USER_AGENT};
+    use crate::test_support::Unreachable;
+
+    #[test]
+    fn test_build() {
+        let api = ApiBuilder::new()
+            .transport(Unreachable)
+            .user_agent("tests")
+            .header("X-Tenant", "a")
+            .header("X-Tenant", "b")
+            .build()
+            .unwrap();
+        assert_eq!(api.headers[USER_AGENT], "tests");
+        assert_eq!(api.headers.get_all("x-tenant").iter().count(), 2);
+        assert!(ApiBuilder::new().header("X Tenant", "a").build().is_err());
+        assert!(ApiBuilder::new().user_agent("\n").build().is_err());
+    }
+}
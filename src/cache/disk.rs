@@ -0,0 +1,369 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::header::{ETAG, HeaderName, LAST_MODIFIED};
+
+use crate::source::RangeSource;
+use crate::{Api, Error, Prefix, RangeIter, Result, Suffix};
+
+/// the time after which cached responses are revalidated by default
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// the number of prefix characters used for the subdirectory names
+const DIRECTORY_PREFIX_SIZE: usize = 2;
+
+/// a counter to make temporary file names unique within the process
+static TEMPORARY_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// The information saved before a cached response body.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+struct Metadata {
+    /// the unix time in seconds when the response was fetched or revalidated
+    fetched: u64,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl Metadata {
+    fn parse(text: &str) -> Option<Self> {
+        let mut metadata = Metadata::default();
+        let mut has_fetched = false;
+        for line in text.lines() {
+            let (key, value) = line.split_once(": ")?;
+            match key {
+                "fetched" => {
+                    metadata.fetched = value.parse().ok()?;
+                    has_fetched = true;
+                }
+                "etag" => metadata.etag = Some(value.to_string()),
+                "last-modified" => metadata.last_modified = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        has_fetched.then_some(metadata)
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = format!("fetched: {}\n", self.fetched);
+        if let Some(etag) = &self.etag {
+            text.push_str(&format!("etag: {}\n", etag));
+        }
+        if let Some(last_modified) = &self.last_modified {
+            text.push_str(&format!("last-modified: {}\n", last_modified));
+        }
+        text
+    }
+
+    fn age(&self) -> Duration {
+        let fetched = UNIX_EPOCH + Duration::from_secs(self.fetched);
+        SystemTime::now().duration_since(fetched).unwrap_or_default()
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Write a file by renaming a complete temporary file over it,
+/// so that readers never see a partially written file.
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let name = path.file_name().unwrap().to_string_lossy();
+    let temporary = path.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        name,
+        std::process::id(),
+        TEMPORARY_COUNTER.fetch_add(1, Ordering::Relaxed),
+    ));
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temporary);
+    })?;
+    Ok(())
+}
+
+/// Read a file, or `None` if it does not exist.
+fn read_optional(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// A cache of range responses in a directory,
+/// in front of the online [`Api`].
+///
+/// The raw response body of each prefix is saved together with
+/// the time it was fetched and its `ETag` and `Last-Modified` headers.
+/// Responses older than the time-to-live are revalidated with
+/// a conditional request, and a `304 Not Modified` response
+/// refreshes the cached response without downloading it again.
+/// Responses without any range entries are not saved,
+/// and neither are responses that fail
+/// [strict validation](crate::ApiBuilder::strict_validation).
+///
+/// The directory has a file for each cached prefix,
+/// such as `21/21BD1.range` for the prefix `21BD1`.
+/// The file starts with the fetch time (unix seconds) and validators,
+/// as lines of `fetched: ...`, `etag: ...` and `last-modified: ...`,
+/// followed by an empty line and the response body.
+///
+/// Files are written to a temporary file that is then renamed,
+/// so multiple processes can safely share the directory:
+/// readers never see a partial file, and the validators
+/// always belong to the body in the same file.
+///
+/// This reads and writes files while blocking the current thread.
+#[derive(Debug)]
+pub struct DiskCache {
+    api: Api,
+    directory: PathBuf,
+    ttl: Duration,
+}
+
+impl DiskCache {
+    /// Create a cache in a directory, which is created if needed.
+    ///
+    /// Cached responses are revalidated after one day by default.
+    pub fn new(api: Api, directory: impl Into<PathBuf>) -> Result<Self> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+        Ok(Self { api, directory, ttl: DEFAULT_TTL })
+    }
+
+    /// Set the time after which cached responses are revalidated.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Get the API.
+    pub fn api(&self) -> &Api {
+        &self.api
+    }
+
+    /// Get the cache directory.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Get the path of the cached response of a prefix.
+    fn path(&self, prefix: Prefix) -> PathBuf {
+        self.directory
+            .join(&prefix.as_str()[..DIRECTORY_PREFIX_SIZE])
+            .join(format!("{}.range", prefix))
+    }
+
+    /// Remove the cached response of a prefix.
+    pub fn invalidate(&self, prefix: Prefix) -> Result<()> {
+        match fs::remove_file(self.path(prefix)) {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Read the cached response body and metadata of a prefix.
+    fn load(&self, prefix: Prefix) -> Result<Option<(Bytes, Metadata)>> {
+        let Some(contents) = read_optional(&self.path(prefix))? else {
+            return Ok(None)
+        };
+        // an unreadable file is treated as a missing one
+        let Some(split) = contents.windows(2).position(|bytes| bytes == b"\n\n") else {
+            return Ok(None)
+        };
+        let metadata = std::str::from_utf8(&contents[..split]).ok()
+            .and_then(Metadata::parse);
+        let body = Bytes::from(contents).slice((split + 2)..);
+        Ok(metadata.map(|metadata| (body, metadata)))
+    }
+
+    /// Save the response body and metadata of a prefix.
+    fn store(&self, prefix: Prefix, body: &[u8], metadata: &Metadata) -> Result<()> {
+        let path = self.path(prefix);
+        fs::create_dir_all(path.parent().unwrap())?;
+        let mut contents = metadata.to_text().into_bytes();
+        contents.push(b'\n');
+        contents.extend_from_slice(body);
+        write_atomic(&path, &contents)
+    }
+
+    /// Get the response body of a prefix,
+    /// from the cache or from the API.
+    ///
+    /// Fails with [`Error::MalformedResponse`]
+    /// if a fetched response does not have any range entries.
+    pub async fn range_bytes(&self, prefix: Prefix) -> Result<Bytes> {
+        let cached = self.load(prefix)?;
+        if let Some((body, metadata)) = &cached {
+            if metadata.age() < self.ttl {
+                return Ok(body.clone())
+            }
+        }
+        let (etag, last_modified) = match &cached {
+            Some((_body, metadata)) => {
+                (metadata.etag.as_deref(), metadata.last_modified.as_deref())
+            }
+            None => (None, None),
+        };
+        let response = self.api
+            .range_bytes_if_modified(prefix, etag, last_modified)
+            .await?;
+        match (response, cached) {
+            (None, Some((body, mut metadata))) => {
+                metadata.fetched = unix_time();
+                self.store(prefix, &body, &metadata)?;
+                Ok(body)
+            }
+            (None, None) => Err(Error::from_status(304, None)),
+            (Some((headers, body)), _) => {
+                // do not keep an unrelated page, such as from a captive portal
+                if !RangeIter::new(body.clone()).any(|result| result.is_ok()) {
+                    return Err(Error::MalformedResponse)
+                }
+                let header = |name: HeaderName| {
+                    headers.get(name)
+                        .and_then(|value| value.to_str().ok())
+                        .map(str::to_string)
+                };
+                let metadata = Metadata {
+                    fetched: unix_time(),
+                    etag: header(ETAG),
+                    last_modified: header(LAST_MODIFIED),
+                };
+                self.store(prefix, &body, &metadata)?;
+                Ok(body)
+            }
+        }
+    }
+}
+
+/// Fails with [`Error::MalformedResponse`]
+/// if none of the lines in the response can be parsed.
+#[async_trait]
+impl RangeSource for DiskCache {
+    async fn fetch_range(&self, prefix: Prefix) -> Result<Vec<(Suffix, u32)>> {
+        let body = self.range_bytes(prefix).await?;
+        let range = RangeIter::new(body)
+            .filter_map(|result| result.ok())
+            .collect::<Vec<_>>();
+        if range.is_empty() {
+            Err(Error::MalformedResponse)
+        } else {
+            Ok(range)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use http::header::{ETAG, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH};
+
+    use super::{DiskCache, Metadata, unix_time};
+    use crate::source::count_breaches;
+    use crate::test_support::{FixedRange, Unreachable};
+    use crate::transport::{Request, Response, Transport};
+    use crate::{Api, Error, hash, Result};
+
+    /// Answers conditional requests with `304 Not Modified`,
+    /// and keeps the requests.
+    #[derive(Debug, Default)]
+    struct NotModified {
+        requests: Mutex<Vec<Request>>,
+    }
+
+    #[async_trait]
+    impl Transport for NotModified {
+        async fn get(&self, request: Request) -> Result<Response> {
+            let is_conditional = request.headers.contains_key(IF_NONE_MATCH);
+            self.requests.lock().unwrap().push(request);
+            if is_conditional {
+                Ok(Response::new(304, Default::default(), ""))
+            } else {
+                let mut headers = http::HeaderMap::new();
+                headers.insert(ETAG, HeaderValue::from_static("\"abc\""));
+                let body = "2DC183F740EE76F27B78EB39C8AD972A757:52579\r\n";
+                Ok(Response::new(200, headers, body))
+            }
+        }
+    }
+
+    #[test]
+    fn test_disk_cache() {
+        let directory = std::env::temp_dir()
+            .join(format!("passleak-cache-{}", std::process::id()));
+        let api = Api::builder().transport(Unreachable).build().unwrap();
+        let cache = DiskCache::new(api, &directory).unwrap();
+        let (prefix, suffix) = hash("P@ssw0rd");
+        let body = format!("{}:52579\r\n", suffix);
+        let metadata = Metadata {
+            fetched: unix_time(),
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+        };
+        cache.store(prefix, body.as_bytes(), &metadata).unwrap();
+        assert!(directory.join("21").join("21BD1.range").exists());
+        assert_eq!(cache.load(prefix).unwrap().unwrap(), (body.into(), metadata));
+
+        tokio_test::block_on(async {
+            assert_eq!(count_breaches(&cache, "P@ssw0rd").await.unwrap(), 52579);
+            let cache = DiskCache::new(cache.api().clone(), &directory)
+                .unwrap()
+                .with_ttl(Duration::ZERO);
+            // an expired response is revalidated, which fails here
+            assert!(count_breaches(&cache, "P@ssw0rd").await.is_err());
+            cache.invalidate(prefix).unwrap();
+            assert!(cache.load(prefix).unwrap().is_none());
+
+            // a page without range entries is not cached
+            let api = Api::builder().transport(FixedRange("<html></html>")).build().unwrap();
+            let cache = DiskCache::new(api, &directory).unwrap();
+            let result = cache.range_bytes(prefix).await;
+            assert!(matches!(result, Err(Error::MalformedResponse)));
+            assert!(cache.load(prefix).unwrap().is_none());
+        });
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_revalidate() {
+        let directory = std::env::temp_dir()
+            .join(format!("passleak-revalidate-{}", std::process::id()));
+        let transport = Arc::new(NotModified::default());
+        let api = Api::builder().transport(transport.clone()).build().unwrap();
+        let cache = DiskCache::new(api, &directory).unwrap();
+        let (prefix, _suffix) = hash("P@ssw0rd");
+        tokio_test::block_on(async {
+            assert_eq!(count_breaches(&cache, "P@ssw0rd").await.unwrap(), 52579);
+            let (body, mut metadata) = cache.load(prefix).unwrap().unwrap();
+            assert_eq!(metadata.etag.as_deref(), Some("\"abc\""));
+
+            // make the cached response expire
+            metadata.fetched = 0;
+            metadata.last_modified = Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string());
+            cache.store(prefix, &body, &metadata).unwrap();
+            assert_eq!(count_breaches(&cache, "P@ssw0rd").await.unwrap(), 52579);
+            let (revalidated, metadata) = cache.load(prefix).unwrap().unwrap();
+            assert_eq!(revalidated, body);
+            assert!(metadata.fetched > 0);
+            assert_eq!(metadata.etag.as_deref(), Some("\"abc\""));
+        });
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0].headers.contains_key(IF_NONE_MATCH));
+        assert_eq!(requests[1].headers[IF_NONE_MATCH], "\"abc\"");
+        assert_eq!(requests[1].headers[IF_MODIFIED_SINCE], "Wed, 21 Oct 2015 07:28:00 GMT");
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
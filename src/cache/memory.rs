@@ -0,0 +1,320 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+use crate::source::RangeSource;
+use crate::{Hash, hash, Password, Prefix, Result, scan_range, Suffix};
+
+/// the estimated size of a cached range apart from its entries
+const RANGE_OVERHEAD: usize = 64;
+
+/// The size limit of a [`RangeCache`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CacheLimit {
+    /// The maximum number of cached ranges.
+    Entries(usize),
+    /// The maximum estimated memory use of the cached ranges in bytes.
+    Bytes(usize),
+}
+
+/// The statistics of a [`RangeCache`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct CacheStats {
+    /// The number of lookups answered by the cache.
+    pub hits: u64,
+    /// The number of lookups that fetched the range from the source.
+    pub misses: u64,
+    /// The number of cached ranges.
+    pub entries: usize,
+    /// The estimated memory use of the cached ranges in bytes.
+    pub bytes: usize,
+}
+
+struct CachedRange {
+    range: Arc<[(Suffix, u32)]>,
+    fetched: Instant,
+    /// the last use, for finding the least recently used range
+    tick: u64,
+}
+
+#[derive(Default)]
+struct State {
+    ranges: HashMap<Prefix, CachedRange>,
+    /// the prefixes by last use
+    order: BTreeMap<u64, Prefix>,
+    tick: u64,
+    bytes: usize,
+}
+
+impl State {
+    fn remove(&mut self, prefix: Prefix) {
+        if let Some(cached) = self.ranges.remove(&prefix) {
+            self.order.remove(&cached.tick);
+            self.bytes -= range_size(&cached.range);
+        }
+    }
+}
+
+fn range_size(range: &[(Suffix, u32)]) -> usize {
+    RANGE_OVERHEAD + std::mem::size_of_val(range)
+}
+
+/// An in-memory cache in front of a [`RangeSource`],
+/// keyed by [`Prefix`].
+///
+/// The least recently used ranges are removed when the cache is full,
+/// and ranges older than the time-to-live are fetched again.
+/// Failed fetches are not cached.
+///
+/// The cache can be shared between tasks.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "reqwest")] {
+/// use std::time::Duration;
+/// use passleak::Api;
+/// use passleak::cache::{CacheLimit, RangeCache};
+///
+/// let api = RangeCache::new(Api::new(), CacheLimit::Entries(10_000))
+///     .with_ttl(Duration::from_secs(24 * 60 * 60));
+/// # }
+/// ```
+pub struct RangeCache<S> {
+    source: S,
+    limit: CacheLimit,
+    ttl: Option<Duration>,
+    state: Mutex<State>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<S> RangeCache<S> {
+    /// Create a cache in front of a source.
+    ///
+    /// Cached ranges do not expire by default.
+    pub fn new(source: S, limit: CacheLimit) -> Self {
+        Self {
+            source,
+            limit,
+            ttl: None,
+            state: Mutex::new(State::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Set the time after which cached ranges are fetched again.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Get the source.
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// Get the statistics of the cache.
+    pub fn stats(&self) -> CacheStats {
+        let state = self.state.lock().unwrap();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: state.ranges.len(),
+            bytes: state.bytes,
+        }
+    }
+
+    /// Remove the cached range of a prefix.
+    pub fn invalidate(&self, prefix: Prefix) {
+        self.state.lock().unwrap().remove(prefix);
+    }
+
+    /// Remove all cached ranges.
+    pub fn clear(&self) {
+        *self.state.lock().unwrap() = State::default();
+    }
+
+    /// Add a range to the cache.
+    pub fn insert(&self, prefix: Prefix, range: Vec<(Suffix, u32)>) {
+        self.insert_shared(prefix, Arc::from(range));
+    }
+
+    fn insert_shared(&self, prefix: Prefix, range: Arc<[(Suffix, u32)]>) {
+        let mut state = self.state.lock().unwrap();
+        state.remove(prefix);
+        state.tick += 1;
+        let tick = state.tick;
+        state.bytes += range_size(&range);
+        state.order.insert(tick, prefix);
+        state.ranges.insert(prefix, CachedRange {
+            range,
+            fetched: Instant::now(),
+            tick,
+        });
+        // remove the least recently used ranges, but keep the new one
+        while state.ranges.len() > 1 && self.is_over_limit(&state) {
+            let oldest = *state.order.values().next().unwrap();
+            state.remove(oldest);
+        }
+    }
+
+    fn is_over_limit(&self, state: &State) -> bool {
+        match self.limit {
+            CacheLimit::Entries(entries) => state.ranges.len() > entries,
+            CacheLimit::Bytes(bytes) => state.bytes > bytes,
+        }
+    }
+
+    /// Get a cached range that has not expired, and mark it as used.
+    fn get(&self, prefix: Prefix) -> Option<Arc<[(Suffix, u32)]>> {
+        let mut state = self.state.lock().unwrap();
+        let cached = state.ranges.get(&prefix)?;
+        if self.ttl.is_some_and(|ttl| cached.fetched.elapsed() >= ttl) {
+            state.remove(prefix);
+            return None
+        }
+        let previous = cached.tick;
+        state.tick += 1;
+        let tick = state.tick;
+        state.order.remove(&previous);
+        state.order.insert(tick, prefix);
+        let cached = state.ranges.get_mut(&prefix).unwrap();
+        cached.tick = tick;
+        Some(cached.range.clone())
+    }
+}
+
+impl<S: RangeSource> RangeCache<S> {
+    /// Get the range of a prefix from the cache,
+    /// or fetch and cache it if it is not cached.
+    ///
+    /// Unlike [`RangeSource::fetch_range`],
+    /// this does not copy the cached range.
+    pub async fn fetch_range_shared(&self, prefix: Prefix) -> Result<Arc<[(Suffix, u32)]>> {
+        if let Some(range) = self.get(prefix) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(range)
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let range = Arc::<[_]>::from(self.source.fetch_range(prefix).await?);
+        self.insert_shared(prefix, range.clone());
+        Ok(range)
+    }
+
+    /// Count the number of known breaches for a password,
+    /// without copying the cached range.
+    ///
+    /// Every entry of the range is compared in constant time.
+    pub async fn count_breaches<P: Password + ?Sized>(&self, password: &P) -> Result<u32> {
+        self.count_breaches_for_hash(&Hash::from(hash(password))).await
+    }
+
+    /// Count the number of known breaches for a SHA-1 password hash,
+    /// without copying the cached range.
+    ///
+    /// Every entry of the range is compared in constant time.
+    pub async fn count_breaches_for_hash(&self, hash: &Hash) -> Result<u32> {
+        let range = self.fetch_range_shared(hash.prefix()).await?;
+        Ok(scan_range(hash.suffix(), range.iter().cloned(), true).unwrap_or(0))
+    }
+
+    /// Fetch and cache the ranges of some prefixes ahead of time.
+    pub async fn prewarm(
+        &self,
+        prefixes: impl IntoIterator<Item=Prefix>,
+    ) -> Result<()> {
+        for prefix in prefixes {
+            let range = self.source.fetch_range(prefix).await?;
+            self.insert(prefix, range);
+        }
+        Ok(())
+    }
+}
+
+/// This copies the cached range,
+/// use [`RangeCache::fetch_range_shared`] or [`RangeCache::count_breaches`]
+/// to read it without copying.
+#[async_trait]
+impl<S: RangeSource> RangeSource for RangeCache<S> {
+    async fn fetch_range(&self, prefix: Prefix) -> Result<Vec<(Suffix, u32)>> {
+        Ok(self.fetch_range_shared(prefix).await?.to_vec())
+    }
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for RangeCache<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RangeCache")
+            .field("source", &self.source)
+            .field("limit", &self.limit)
+            .field("ttl", &self.ttl)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+
+    use super::{CacheLimit, RangeCache};
+    use crate::source::{count_breaches, RangeSource};
+    use crate::{hash, Prefix, Result, Suffix};
+
+    /// A source with a single breached password that counts its fetches.
+    #[derive(Default)]
+    struct Counting(AtomicU64);
+
+    #[async_trait]
+    impl RangeSource for Counting {
+        async fn fetch_range(&self, prefix: Prefix) -> Result<Vec<(Suffix, u32)>> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            let (breached, suffix) = hash("P@ssw0rd");
+            Ok(if prefix == breached { vec![(suffix, 3)] } else { vec![] })
+        }
+    }
+
+    #[test]
+    fn test_cache() {
+        let cache = RangeCache::new(Counting::default(), CacheLimit::Entries(2));
+        tokio_test::block_on(async {
+            assert_eq!(count_breaches(&cache, "P@ssw0rd").await.unwrap(), 3);
+            assert_eq!(count_breaches(&cache, "P@ssw0rd").await.unwrap(), 3);
+            assert_eq!(cache.source().0.load(Ordering::Relaxed), 1);
+            let stats = cache.stats();
+            assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+
+            // the least recently used range is removed
+            count_breaches(&cache, "a").await.unwrap();
+            count_breaches(&cache, "P@ssw0rd").await.unwrap();
+            count_breaches(&cache, "b").await.unwrap();
+            assert!(cache.get(hash("P@ssw0rd").0).is_some());
+            assert!(cache.get(hash("a").0).is_none());
+            assert_eq!(cache.stats().entries, 2);
+
+            cache.invalidate(hash("P@ssw0rd").0);
+            assert!(cache.get(hash("P@ssw0rd").0).is_none());
+            cache.prewarm([hash("P@ssw0rd").0]).await.unwrap();
+            assert!(cache.get(hash("P@ssw0rd").0).is_some());
+            let hits = cache.stats().hits;
+            assert_eq!(cache.count_breaches("P@ssw0rd").await.unwrap(), 3);
+            assert_eq!(cache.count_breaches("a").await.unwrap(), 0);
+            assert_eq!(cache.stats().hits, hits + 1);
+        });
+
+        let cache = RangeCache::new(Counting::default(), CacheLimit::Bytes(1 << 20))
+            .with_ttl(Duration::ZERO);
+        tokio_test::block_on(async {
+            count_breaches(&cache, "P@ssw0rd").await.unwrap();
+            count_breaches(&cache, "P@ssw0rd").await.unwrap();
+        });
+        assert_eq!(cache.source().0.load(Ordering::Relaxed), 2);
+    }
+}
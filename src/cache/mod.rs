@@ -0,0 +1,10 @@
+//! Caches of password ranges.
+//!
+//! A [`RangeCache`] keeps ranges in memory for a single process,
+//! a [`DiskCache`] keeps range responses on disk across restarts.
+
+mod disk;
+mod memory;
+
+pub use disk::DiskCache;
+pub use memory::{CacheLimit, CacheStats, RangeCache};
@@ -0,0 +1,261 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::offline::CorpusError;
+use crate::RangeDiagnostics;
+
+/// A result with the crate [`Error`] type.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The errors that can occur while querying the API.
+///
+/// Note that none of these errors mean that a password is not breached,
+/// they only mean that the answer is not known.
+///
+/// The errors of range requests are wrapped in [`Error::Shared`],
+/// by both the async and the blocking API,
+/// so match on [`Error::inner`] to check the kind of error.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The request could not be sent or the response could not be received.
+    #[cfg(feature = "reqwest")]
+    Request(reqwest::Error),
+    /// A custom [`Transport`](crate::transport::Transport)
+    /// could not send the request or receive the response.
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+    /// The service responded with a non-success status code.
+    Status {
+        /// The HTTP status code.
+        status: u16,
+        /// The delay requested by the `Retry-After` header, if any.
+        retry_after: Option<Duration>,
+    },
+    /// The service is rate limiting requests (status code 429).
+    RateLimited {
+        /// The delay requested by the `Retry-After` header, if any.
+        retry_after: Option<Duration>,
+    },
+    /// The response body does not contain any range entries.
+    MalformedResponse,
+    /// The response body failed
+    /// [strict validation](crate::ApiBuilder::strict_validation).
+    InvalidRange(RangeDiagnostics),
+    /// The API configuration is invalid.
+    Config(String),
+    /// Reading or writing a local file failed.
+    Io(std::io::Error),
+    /// A binary corpus is invalid.
+    Corpus(CorpusError),
+    /// The error of a range request,
+    /// which is shared by concurrent lookups of the same range.
+    ///
+    /// Use [`Error::inner`] to get the error itself.
+    Shared(Arc<Error>),
+    /// The request did not complete before the
+    /// [retry deadline](crate::RetryPolicy::deadline).
+    DeadlineExceeded,
+    /// The request failed after being [retried](crate::RetryPolicy).
+    RetriesExhausted {
+        /// The number of attempts, including the first one.
+        attempts: u32,
+        /// The error of the last attempt.
+        error: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Create the error for a non-success response.
+    pub(crate) fn from_status(
+        status: u16,
+        retry_after: Option<Duration>,
+    ) -> Self {
+        if status == 429 {
+            Error::RateLimited { retry_after }
+        } else {
+            Error::Status { status, retry_after }
+        }
+    }
+
+    /// Get the error without any [`Error::Shared`] wrapper,
+    /// for example to match on the kind of error.
+    pub fn inner(&self) -> &Error {
+        match self {
+            Error::Shared(error) => error.inner(),
+            error => error,
+        }
+    }
+
+    /// The delay the service asked for before trying again, if any.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::Status { retry_after, .. } => *retry_after,
+            Error::RateLimited { retry_after } => *retry_after,
+            Error::Shared(error) => error.retry_after(),
+            Error::RetriesExhausted { error, .. } => error.retry_after(),
+            _ => None,
+        }
+    }
+
+    /// The HTTP status code of a non-success response, if any.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Error::Status { status, .. } => Some(*status),
+            Error::RateLimited { .. } => Some(429),
+            Error::Shared(error) => error.status(),
+            Error::RetriesExhausted { error, .. } => error.status(),
+            _ => None,
+        }
+    }
+
+    /// Check if the request may succeed when it is sent again.
+    ///
+    /// This is the case for failures to send the request or
+    /// receive the response, rate limiting and server errors
+    /// that are usually temporary (status codes 500, 502, 503 and 504).
+    /// Range requests are idempotent, so these can always be retried.
+    /// Errors that exhausted the retries are transient
+    /// if the error of the last attempt is.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            #[cfg(feature = "reqwest")]
+            Error::Request(error) => {
+                error.is_timeout() || error.is_connect()
+                    || error.is_request() || error.is_body()
+            }
+            Error::Transport(_) => true,
+            Error::Status { status, .. } => matches!(status, 500 | 502 | 503 | 504),
+            Error::RateLimited { .. } => true,
+            Error::DeadlineExceeded => true,
+            Error::Shared(error) => error.is_transient(),
+            Error::RetriesExhausted { error, .. } => error.is_transient(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "reqwest")]
+            Error::Request(error) => write!(f, "request failed: {}", error),
+            Error::Transport(error) => write!(f, "request failed: {}", error),
+            Error::Status { status, .. } => {
+                write!(f, "service responded with status {}", status)
+            }
+            Error::RateLimited { .. } => write!(f, "rate limited by service"),
+            Error::MalformedResponse => write!(f, "malformed range response"),
+            Error::InvalidRange(diagnostics) => {
+                write!(f, "invalid range response: {}", diagnostics)
+            }
+            Error::Config(message) => {
+                write!(f, "invalid configuration: {}", message)
+            }
+            Error::Io(error) => write!(f, "io error: {}", error),
+            Error::Corpus(error) => error.fmt(f),
+            Error::Shared(error) => error.fmt(f),
+            Error::DeadlineExceeded => write!(f, "retry deadline exceeded"),
+            Error::RetriesExhausted { attempts, error } => {
+                write!(f, "{} (gave up after {} attempts)", error, attempts)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "reqwest")]
+            Error::Request(error) => Some(error),
+            Error::Transport(error) => Some(&**error),
+            Error::Io(error) => Some(error),
+            Error::Corpus(error) => Some(error),
+            Error::Shared(error) => error.source(),
+            Error::RetriesExhausted { error, .. } => Some(&**error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        Error::Request(error)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<CorpusError> for Error {
+    fn from(error: CorpusError) -> Self {
+        Error::Corpus(error)
+    }
+}
+
+/// The error when parsing a password hash.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParseHashError {
+    /// The input does not have the number of characters of the hash.
+    InvalidLength {
+        /// The expected number of characters.
+        expected: usize,
+        /// The number of characters of the input.
+        found: usize,
+    },
+    /// The input contains characters that are not base16.
+    InvalidCharacter,
+}
+
+impl fmt::Display for ParseHashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseHashError::InvalidLength { expected, found } => write!(
+                f,
+                "invalid hash length {}, expected {} characters",
+                found,
+                expected,
+            ),
+            ParseHashError::InvalidCharacter => {
+                write!(f, "invalid base16 character in hash")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseHashError {}
+
+/// Parse the value of a `Retry-After` header.
+///
+/// This is either a number of seconds or an HTTP date.
+pub(crate) fn parse_retry_after(value: &[u8]) -> Option<Duration> {
+    let value = std::str::from_utf8(value).ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        Some(Duration::from_secs(seconds))
+    } else {
+        let date = httpdate::parse_http_date(value).ok()?;
+        Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Duration, Error, parse_retry_after};
+
+    #[test]
+    fn test_retry_after() {
+        assert_eq!(parse_retry_after(b"120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after(b"Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO),
+        );
+        assert_eq!(parse_retry_after(b"soon"), None);
+        let error = Error::from_status(429, Some(Duration::from_secs(2)));
+        assert!(matches!(error, Error::RateLimited { .. }));
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(2)));
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+
+use crate::{Error, Mode, Prefix, Result};
+
+type SharedFetch = Shared<BoxFuture<'static, Result<Bytes, Arc<Error>>>>;
+
+/// the prefix, mode and padding of a range request
+type Key = (Prefix, Mode, bool);
+
+/// The range requests that are in flight,
+/// so concurrent lookups of the same range share a single request.
+///
+/// Clones share the requests in flight,
+/// which are keyed by whether the response is padded
+/// because clones can differ in that setting.
+#[derive(Clone, Default)]
+pub(crate) struct InFlight {
+    fetches: Arc<Mutex<Fetches>>,
+}
+
+#[derive(Default)]
+struct Fetches {
+    /// the requests in flight, with a unique id
+    /// to recognize them after they completed
+    requests: HashMap<Key, (u64, SharedFetch)>,
+    next_id: u64,
+}
+
+impl InFlight {
+    /// Get a range response body using the request in flight,
+    /// or start the given request if there is none.
+    ///
+    /// The request continues if the caller is cancelled,
+    /// as long as other callers are waiting for it.
+    /// Every waiting caller receives the response or the error,
+    /// which is always wrapped in [`Error::Shared`]
+    /// so it does not depend on the number of callers.
+    pub(crate) async fn fetch<F>(
+        &self,
+        prefix: Prefix,
+        mode: Mode,
+        padded: bool,
+        fetch: F,
+    ) -> Result<Bytes>
+    where
+        F: Future<Output=Result<Bytes>> + Send + 'static,
+    {
+        let key = (prefix, mode, padded);
+        let (id, shared) = {
+            let mut fetches = self.fetches.lock().unwrap();
+            let Fetches { requests, next_id } = &mut *fetches;
+            requests.entry(key)
+                .or_insert_with(|| {
+                    *next_id += 1;
+                    let fetch = fetch.map(|result| result.map_err(Arc::new));
+                    (*next_id, fetch.boxed().shared())
+                })
+                .clone()
+        };
+        let mut waiter = Waiter { in_flight: self, key, id, shared };
+        (&mut waiter.shared).await.map_err(Error::Shared)
+    }
+}
+
+impl fmt::Debug for InFlight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fetches = self.fetches.lock().unwrap();
+        f.debug_struct("InFlight")
+            .field("requests", &fetches.requests.len())
+            .finish()
+    }
+}
+
+/// Removes a request when it completed,
+/// or when the last waiting caller is cancelled.
+struct Waiter<'a> {
+    in_flight: &'a InFlight,
+    key: Key,
+    id: u64,
+    shared: SharedFetch,
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        let mut fetches = self.in_flight.fetches.lock().unwrap();
+        if let Some((id, shared)) = fetches.requests.get(&self.key) {
+            // the count includes this waiter and the map itself
+            let is_last = self.shared.strong_count().is_none_or(|count| count <= 2);
+            if *id == self.id && (is_last || shared.peek().is_some()) {
+                fetches.requests.remove(&self.key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use futures_timer::Delay;
+    use futures_util::future::{join, join3, select};
+
+    use super::InFlight;
+    use crate::{Error, hash, Mode, Result};
+
+    /// Count the fetches, and answer after a short delay
+    /// so all callers are waiting.
+    async fn fetch(fetches: Arc<AtomicUsize>, result: Result<Bytes>) -> Result<Bytes> {
+        fetches.fetch_add(1, Ordering::SeqCst);
+        Delay::new(Duration::from_millis(100)).await;
+        result
+    }
+
+    #[test]
+    fn test_cancel() {
+        let in_flight = InFlight::default();
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let (prefix, _suffix) = hash("P@ssw0rd");
+        let body = || Ok(Bytes::from_static(b"body"));
+        tokio_test::block_on(async {
+            let first = in_flight.fetch(prefix, Mode::Sha1, true, fetch(fetches.clone(), body()));
+            let second = in_flight.fetch(prefix, Mode::Sha1, true, fetch(fetches.clone(), body()));
+            // the first caller starts the fetch, then is cancelled
+            let cancel = async {
+                let first = std::pin::pin!(first);
+                let delay = Delay::new(Duration::from_millis(10));
+                let _ = select(first, delay).await;
+            };
+            let ((), second) = join(cancel, second).await;
+            assert_eq!(second.unwrap(), "body");
+        });
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(in_flight.fetches.lock().unwrap().requests.is_empty());
+
+        // padded and unpadded responses are not shared
+        let (padded, unpadded) = tokio_test::block_on(join(
+            in_flight.fetch(prefix, Mode::Sha1, true, fetch(fetches.clone(), body())),
+            in_flight.fetch(prefix, Mode::Sha1, false, fetch(fetches.clone(), body())),
+        ));
+        assert!(padded.is_ok() && unpadded.is_ok());
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_error() {
+        let in_flight = InFlight::default();
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let (prefix, _suffix) = hash("P@ssw0rd");
+        let error = || Err(Error::Transport("unreachable".into()));
+        let results = tokio_test::block_on(join3(
+            in_flight.fetch(prefix, Mode::Sha1, true, fetch(fetches.clone(), error())),
+            in_flight.fetch(prefix, Mode::Sha1, true, fetch(fetches.clone(), error())),
+            in_flight.fetch(prefix, Mode::Sha1, true, fetch(fetches.clone(), error())),
+        ));
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        for result in [results.0, results.1, results.2] {
+            let error = result.unwrap_err();
+            assert!(matches!(error, Error::Shared(_)));
+            assert!(matches!(error.inner(), Error::Transport(_)));
+        }
+        // a single caller gets the same kind of error
+        let result = tokio_test::block_on(
+            in_flight.fetch(prefix, Mode::Sha1, true, fetch(fetches.clone(), error())),
+        );
+        assert!(matches!(result, Err(Error::Shared(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "reqwest")]
+    fn test_single_request() {
+        use crate::Api;
+        use crate::test_support::range_server;
+
+        // answer slowly, so all lookups are in flight
+        let (base_url, requests) = range_server(Duration::from_millis(200));
+        let api = Api::with_base_url(&base_url).unwrap();
+        let clone = api.clone();
+        tokio_test::block_on(async {
+            let (first, second, third) = join3(
+                api.count_breaches("P@ssw0rd"),
+                api.count_breaches("P@ssw0rd"),
+                clone.is_breached("P@ssw0rd"),
+            ).await;
+            assert_eq!(first.unwrap(), 52579);
+            assert_eq!(second.unwrap(), 52579);
+            assert!(third.unwrap());
+        });
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        assert_eq!(api.in_flight.fetches.lock().unwrap().requests.len(), 0);
+    }
+}
@@ -2,15 +2,36 @@
 //! provided by "Have I Been Pwned".
 //!
 //! Features:
-//!   * Async using [`tokio`](https://tokio.rs/) and [`reqwest`].
+//!   * Async using [`tokio`](https://tokio.rs/) and [`reqwest`],
+//!     or any runtime and HTTP client through a [custom transport](transport).
 //!   * Brotli compression for reduced data usage.
+//!   * Lookups by SHA-1 or NTLM password hash.
+//!   * Offline lookups in a downloaded copy of the database.
+//!   * A [fallback chain](source::Fallback) of online, mirror and offline sources.
+//!   * Batch lookups that fetch each range only once.
+//!   * A configurable [failure policy](FailurePolicy)
+//!     for when the service is unavailable.
 //!   * Password hash prefix leak prevention by padding responses.
-//!   * Constant time base16 encoding and password suffix comparison
-//!     to prevent any timing atacks.
+//!   * Constant time base16 encoding, password suffix comparison
+//!     and range response scanning to prevent any timing atacks.
+//!   * Optional [strict validation](ApiBuilder::strict_validation)
+//!     of range responses, to detect tampering proxies.
+//!
+//! Cargo features:
+//!   * `reqwest` (default): The default [transport](transport::ReqwestTransport)
+//!     using [`reqwest`].
+//!   * `blocking`: A synchronous `blocking::Api`
+//!     for applications without an async runtime.
+//!   * `testing`: A fake service (`testing::FakeTransport`)
+//!     for testing applications without network access.
+//!   * `mmap`: Memory-mapped [binary corpus](offline::BinaryCorpus) files.
+//!     This is the only feature that requires unsafe code, to map the file.
+//!     Without it, unsafe code is forbidden.
 //!
 //! # Examples
 //!
 //! ```
+//! # #[cfg(feature = "reqwest")] {
 //! # tokio_test::block_on(async {
 //! use passleak::Api;
 //!
@@ -24,48 +45,333 @@
 //! let is_breached = api.is_breached("secret").await.expect("api error");
 //! assert!(is_breached);
 //! # })
+//! # }
 //! ```
 
+#![cfg_attr(not(feature = "mmap"), forbid(unsafe_code))]
+#![cfg_attr(feature = "mmap", deny(unsafe_code))]
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use md4::Md4;
 use sha1::{Digest, Sha1};
 use bytes::Bytes;
-use subtle::ConstantTimeEq;
-use reqwest::Client;
+use futures_util::stream::Stream;
+use subtle::{ConditionallySelectable, ConstantTimeEq};
+use http::header::{HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, RETRY_AFTER};
+use url::Url;
+
+use flight::InFlight;
+use policy::FailOpenHook;
+use stream::{find_in_stream, RangeStream};
+use transport::{Body, BodyStream, HeaderMap, Request, Response, Transport};
+use validate::validate_range;
+
+mod batch;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod builder;
+pub mod cache;
+mod error;
+mod flight;
+pub mod offline;
+mod policy;
+mod prefilter;
+mod retry;
+pub mod source;
+mod stream;
+#[cfg(test)]
+mod test_support;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod transport;
+mod validate;
+
+pub use batch::Batch;
+pub use builder::ApiBuilder;
+pub use error::{Error, ParseHashError, Result};
+pub use policy::{BreachStatus, FailurePolicy};
+pub use prefilter::{PrefilteredApi, PrefilterStats};
+pub use retry::RetryPolicy;
+pub use source::RangeSource;
+pub use validate::{RangeDiagnostics, RangeIssue};
 
 /// these sizes are in base16 characters (ie. twice the size in bytes)
 const HASH_SIZE: usize = 40;
 const PREFIX_SIZE: usize = 5;
 const SUFFIX_SIZE: usize = HASH_SIZE - PREFIX_SIZE;
+const NTLM_HASH_SIZE: usize = 32;
+const NTLM_SUFFIX_SIZE: usize = NTLM_HASH_SIZE - PREFIX_SIZE;
 
 /// the character in a range response that separates the suffix from the count
 const LINE_SEPARATOR: u8 = b':';
 
+/// The base URL of the public "Have I Been Pwned" passwords service.
+pub const DEFAULT_BASE_URL: &str = "https://api.pwnedpasswords.com/";
+
 /// The first 5 characters of a password hash.
 ///
 /// Comparing values is **not** a constant-time operation.
 ///
 /// This value is hashable and orderable so it can be used
 /// in hashmaps and btrees as a caching key for lookups.
-#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Prefix([u8; PREFIX_SIZE]);
 
-/// The last 35 characters of a password hash.
+/// The last characters of a password hash.
+///
+/// These are the last 35 characters of a SHA-1 hash by default,
+/// or the last 27 characters of an NTLM hash (see [`NtlmSuffix`]).
 ///
 /// Comparing values is a constant-time operation.
-pub struct Suffix([u8; SUFFIX_SIZE]);
+#[derive(Clone)]
+pub struct Suffix<const N: usize = SUFFIX_SIZE>([u8; N]);
+
+/// The last 27 characters of an NTLM password hash.
+pub type NtlmSuffix = Suffix<NTLM_SUFFIX_SIZE>;
+
+/// A complete password hash in uppercase base16.
+///
+/// This is a SHA-1 hash by default, or an NTLM hash (see [`NtlmHash`]).
+///
+/// Parsing accepts both uppercase and lowercase base16,
+/// decoding happens in constant time.
+///
+/// Comparing values is a constant-time operation for the suffix only.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Hash<const N: usize = SUFFIX_SIZE> {
+    prefix: Prefix,
+    suffix: Suffix<N>,
+}
+
+/// A complete NTLM password hash.
+pub type NtlmHash = Hash<NTLM_SUFFIX_SIZE>;
+
+/// Check that all bytes are uppercase base16 characters.
+fn is_upper_hex(bytes: &[u8]) -> bool {
+    // avoid short-circuiting so the time does not depend on the position
+    bytes.iter().fold(true, |valid, byte| {
+        valid & (byte.is_ascii_digit() | (b'A'..=b'F').contains(byte))
+    })
+}
+
+/// Parse an array of uppercase base16 characters.
+fn parse_upper_hex<const N: usize>(
+    chars: &[u8],
+) -> std::result::Result<[u8; N], ParseHashError> {
+    let chars: [u8; N] = chars.try_into()
+        .map_err(|_| ParseHashError::InvalidLength {
+            expected: N,
+            found: chars.len(),
+        })?;
+    if is_upper_hex(&chars) {
+        Ok(chars)
+    } else {
+        Err(ParseHashError::InvalidCharacter)
+    }
+}
+
+/// the uppercase base16 characters by value
+const HEX_CHARS: &[u8; 16] = b"0123456789ABCDEF";
 
 impl Prefix {
-    fn as_str(&self) -> &str {
+    /// The number of distinct prefixes.
+    pub const COUNT: u32 = 1 << (4 * PREFIX_SIZE);
+
+    /// Get the base16 characters.
+    pub fn as_str(&self) -> &str {
         std::str::from_utf8(&self.0).unwrap()
     }
+
+    /// Create the prefix with a numeric value.
+    ///
+    /// Returns `None` if the value is not below [`Prefix::COUNT`].
+    pub fn from_u32(value: u32) -> Option<Self> {
+        if value < Self::COUNT {
+            let mut chars = [0; PREFIX_SIZE];
+            for (index, char) in chars.iter_mut().enumerate() {
+                let shift = 4 * (PREFIX_SIZE - 1 - index);
+                *char = HEX_CHARS[(value >> shift) as usize & 0xF];
+            }
+            Some(Prefix(chars))
+        } else {
+            None
+        }
+    }
+
+    /// Get the numeric value of the prefix.
+    ///
+    /// This is a value below [`Prefix::COUNT`].
+    pub fn to_u32(self) -> u32 {
+        self.0.iter().fold(0, |value, char| {
+            let digit = (*char as char).to_digit(16).unwrap();
+            (value << 4) | digit
+        })
+    }
 }
 
-impl std::cmp::PartialEq for Suffix {
+impl std::fmt::Display for Prefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::fmt::Debug for Prefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Prefix").field(&self.as_str()).finish()
+    }
+}
+
+/// Parse uppercase base16 characters.
+impl TryFrom<&[u8]> for Prefix {
+    type Error = ParseHashError;
+
+    fn try_from(chars: &[u8]) -> std::result::Result<Self, Self::Error> {
+        parse_upper_hex(chars).map(Prefix)
+    }
+}
+
+/// Parse uppercase base16 characters.
+impl std::str::FromStr for Prefix {
+    type Err = ParseHashError;
+
+    fn from_str(chars: &str) -> std::result::Result<Self, Self::Err> {
+        Self::try_from(chars.as_bytes())
+    }
+}
+
+impl From<Prefix> for u32 {
+    fn from(prefix: Prefix) -> u32 {
+        prefix.to_u32()
+    }
+}
+
+impl<const N: usize> Suffix<N> {
+    /// Get the base16 characters.
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.0).unwrap()
+    }
+}
+
+impl<const N: usize> std::fmt::Display for Suffix<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<const N: usize> std::fmt::Debug for Suffix<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Suffix").field(&self.as_str()).finish()
+    }
+}
+
+/// Parse uppercase base16 characters.
+impl<const N: usize> TryFrom<&[u8]> for Suffix<N> {
+    type Error = ParseHashError;
+
+    fn try_from(chars: &[u8]) -> std::result::Result<Self, Self::Error> {
+        parse_upper_hex(chars).map(Suffix)
+    }
+}
+
+/// Parse uppercase base16 characters.
+impl<const N: usize> std::str::FromStr for Suffix<N> {
+    type Err = ParseHashError;
+
+    fn from_str(chars: &str) -> std::result::Result<Self, Self::Err> {
+        Self::try_from(chars.as_bytes())
+    }
+}
+
+impl<const N: usize> ConstantTimeEq for Suffix<N> {
+    fn ct_eq(&self, other: &Self) -> subtle::Choice {
+        <[u8] as ConstantTimeEq>::ct_eq(&self.0, &other.0)
+    }
+}
+
+impl<const N: usize> std::cmp::PartialEq for Suffix<N> {
     fn eq(&self, other: &Self) -> bool {
-        <[u8] as ConstantTimeEq>::ct_eq(&self.0, &other.0).into()
+        self.ct_eq(other).into()
     }
 }
 
-impl std::cmp::Eq for Suffix {}
+impl<const N: usize> std::cmp::Eq for Suffix<N> {}
+
+impl<const N: usize> Hash<N> {
+    /// Create the hash from its prefix and suffix.
+    pub fn from_parts(prefix: Prefix, suffix: Suffix<N>) -> Self {
+        Hash { prefix, suffix }
+    }
+
+    /// Get the prefix.
+    pub fn prefix(&self) -> Prefix {
+        self.prefix
+    }
+
+    /// Get the suffix.
+    pub fn suffix(&self) -> &Suffix<N> {
+        &self.suffix
+    }
+
+    /// Split the hash into the prefix and suffix.
+    pub fn split(self) -> (Prefix, Suffix<N>) {
+        (self.prefix, self.suffix)
+    }
+}
+
+impl<const N: usize> From<(Prefix, Suffix<N>)> for Hash<N> {
+    fn from((prefix, suffix): (Prefix, Suffix<N>)) -> Self {
+        Hash::from_parts(prefix, suffix)
+    }
+}
+
+impl<const N: usize> std::fmt::Display for Hash<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.prefix, self.suffix)
+    }
+}
+
+impl<const N: usize> std::fmt::Debug for Hash<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Hash").field(&format_args!("{}", self)).finish()
+    }
+}
+
+/// Parse uppercase or lowercase base16 characters.
+impl<const N: usize> TryFrom<&[u8]> for Hash<N> {
+    type Error = ParseHashError;
+
+    fn try_from(hex: &[u8]) -> std::result::Result<Self, Self::Error> {
+        let expected = PREFIX_SIZE + N;
+        if hex.len() != expected {
+            return Err(ParseHashError::InvalidLength {
+                expected,
+                found: hex.len(),
+            })
+        }
+        // decode and encode again to normalize the case in constant time
+        let mut bytes = [0; HASH_SIZE / 2];
+        let bytes = base16ct::mixed::decode(hex, &mut bytes)
+            .map_err(|_| ParseHashError::InvalidCharacter)?;
+        let mut chars = [0; HASH_SIZE];
+        base16ct::upper::encode(bytes, &mut chars).unwrap();
+        let mut prefix = [0; PREFIX_SIZE];
+        let mut suffix = [0; N];
+        prefix.copy_from_slice(&chars[..PREFIX_SIZE]);
+        suffix.copy_from_slice(&chars[PREFIX_SIZE..expected]);
+        Ok(Hash { prefix: Prefix(prefix), suffix: Suffix(suffix) })
+    }
+}
+
+/// Parse uppercase or lowercase base16 characters.
+impl<const N: usize> std::str::FromStr for Hash<N> {
+    type Err = ParseHashError;
+
+    fn from_str(hex: &str) -> std::result::Result<Self, Self::Err> {
+        Self::try_from(hex.as_bytes())
+    }
+}
 
 /// Split an array into two arrays.
 fn split_array<T, const LEN: usize, const LEN1: usize, const LEN2: usize>(
@@ -82,33 +388,155 @@ where
     (part1, part2)
 }
 
+/// A password that can be hashed.
+///
+/// This is implemented for text as well as for arbitrary bytes,
+/// so passwords do not need to be valid UTF-8.
+/// Text is hashed as its UTF-8 encoding.
+pub trait Password {
+    /// Get the bytes to hash.
+    fn password_bytes(&self) -> &[u8];
+}
+
+impl Password for str {
+    fn password_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl Password for String {
+    fn password_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl Password for [u8] {
+    fn password_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl<const N: usize> Password for [u8; N] {
+    fn password_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Password for Vec<u8> {
+    fn password_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+/// Uses the platform encoding of the string,
+/// which is UTF-8 for valid unicode.
+impl Password for std::ffi::OsStr {
+    fn password_bytes(&self) -> &[u8] {
+        self.as_encoded_bytes()
+    }
+}
+
+/// Uses the platform encoding of the string,
+/// which is UTF-8 for valid unicode.
+impl Password for std::ffi::OsString {
+    fn password_bytes(&self) -> &[u8] {
+        self.as_encoded_bytes()
+    }
+}
+
 /// Hash a password into a prefix and suffix.
-pub fn hash(password: &str) -> (Prefix, Suffix) {
+pub fn hash<P: Password + ?Sized>(password: &P) -> (Prefix, Suffix) {
     let mut chars = [0; HASH_SIZE];
-    let hash = Sha1::digest(password.as_bytes());
+    let hash = Sha1::digest(password.password_bytes());
+    base16ct::upper::encode(&hash, &mut chars).unwrap();
+    let (prefix, suffix) = split_array(chars);
+    (Prefix(prefix), Suffix(suffix))
+}
+
+/// Hash a password into a prefix and suffix of its NTLM hash.
+///
+/// The NTLM hash is the MD4 hash of the UTF-16LE encoded password.
+pub fn hash_ntlm(password: &str) -> (Prefix, NtlmSuffix) {
+    let mut chars = [0; NTLM_HASH_SIZE];
+    let utf16 = password.encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect::<Vec<u8>>();
+    let hash = Md4::digest(&utf16);
     base16ct::upper::encode(&hash, &mut chars).unwrap();
     let (prefix, suffix) = split_array(chars);
     (Prefix(prefix), Suffix(suffix))
 }
 
+/// The hash algorithm of a range query.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Mode {
+    Sha1,
+    Ntlm,
+}
+
 /// The API configuration.
+///
+/// Use [`Api::builder`] for anything but the default configuration.
+///
+/// Concurrent lookups of the same range share a single request,
+/// also between clones of an instance.
+/// The errors of range requests are wrapped in [`Error::Shared`],
+/// also when the request was not shared, see [`Error::inner`].
+#[derive(Clone, Debug)]
 pub struct Api {
-    client: Client,
+    transport: Arc<dyn Transport>,
+    in_flight: InFlight,
+    base_url: Url,
+    headers: HeaderMap,
+    timeout: Option<Duration>,
+    retry: RetryPolicy,
     add_padding: bool,
+    constant_time: bool,
+    strict_validation: bool,
+    failure_policy: FailurePolicy,
+    fail_open_hook: FailOpenHook,
+}
+
+#[cfg(feature = "reqwest")]
+impl Default for Api {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Api {
     /// Create a new instance.
+    ///
+    /// This requires the `reqwest` cargo feature (enabled by default),
+    /// use [`Api::builder`] with a custom
+    /// [`transport`](ApiBuilder::transport) otherwise.
+    #[cfg(feature = "reqwest")]
     pub fn new() -> Self {
-        Self::with_client(Client::new())
+        Self::builder().build().unwrap()
+    }
+
+    /// Create a builder to configure a new instance.
+    pub fn builder() -> ApiBuilder {
+        ApiBuilder::new()
     }
 
     /// Create a new instance with a custom [`reqwest::Client`].
-    pub fn with_client(client: Client) -> Self {
-        Self {
-            client,
-            add_padding: true,
-        }
+    #[cfg(feature = "reqwest")]
+    pub fn with_client(client: reqwest::Client) -> Self {
+        Self::builder().client(client).build().unwrap()
+    }
+
+    /// Create a new instance that queries a different service,
+    /// such as a mirror or a local stand-in.
+    ///
+    /// Range requests go to `{base_url}range/{prefix}`,
+    /// the same as for the [default](DEFAULT_BASE_URL) service.
+    ///
+    /// Fails with [`Error::Config`] if the base URL is not
+    /// a valid `http` or `https` URL.
+    #[cfg(feature = "reqwest")]
+    pub fn with_base_url(base_url: &str) -> Result<Self> {
+        Self::builder().base_url(base_url).build()
     }
 
     /// Set whether to enable padded responses.
@@ -121,43 +549,113 @@ impl Api {
     /// 
     /// **Reference:**
     /// <https://www.troyhunt.com/enhancing-pwned-passwords-privacy-with-padding/>
+    #[deprecated(note = "use `ApiBuilder::add_padding` instead")]
     pub fn add_padding(&mut self, add_padding: bool) {
         self.add_padding = add_padding
     }
 
+    /// Get the URL for a password range.
+    fn range_url(&self, prefix: Prefix, mode: Mode) -> Url {
+        range_url(&self.base_url, prefix, mode)
+    }
+
     /// Create the API request for a password range.
-    fn range_request(&self, prefix: Prefix) -> reqwest::RequestBuilder {
-        let url = format!("https://api.pwnedpasswords.com/range/{}", prefix.as_str());
-        let mut request = self.client.get(&url);
+    fn range_request(&self, prefix: Prefix, mode: Mode) -> Request {
+        let mut headers = self.headers.clone();
         if self.add_padding {
-            request = request.header("Add-Padding", "true");
+            headers.insert("add-padding", HeaderValue::from_static("true"));
+        }
+        Request {
+            url: self.range_url(prefix, mode),
+            headers,
+            timeout: self.timeout,
         }
-        request
     }
 
     /// Get the API response for a password range.
-    async fn range_response(&self, prefix: Prefix) -> reqwest::Result<reqwest::Response> {
-        let request = self.range_request(prefix);
-        let response = request.send().await?;
+    ///
+    /// Responses with a non-success status code are turned into errors.
+    /// Failed requests are retried, but not the response body.
+    async fn range_response(&self, prefix: Prefix, mode: Mode) -> Result<Response> {
+        let request = self.range_request(prefix, mode);
+        self.retry.run(|| async {
+            let response = self.transport.get(request.clone()).await?;
+            check_status(response)
+        }).await
+    }
+
+    /// Get the API response body bytes for a password range,
+    /// sharing the request with concurrent calls for the same range.
+    ///
+    /// Failed requests are retried, including the response body.
+    async fn shared_range_bytes(&self, prefix: Prefix, mode: Mode) -> Result<Bytes> {
+        let request = self.range_request(prefix, mode);
+        let transport = self.transport.clone();
+        let retry = self.retry.clone();
+        let padded = self.add_padding;
+        let strict_validation = self.strict_validation;
+        self.in_flight.fetch(prefix, mode, padded, async move {
+            let body = retry.run(|| async {
+                let response = check_status(transport.get(request.clone()).await?)?;
+                response.body.bytes().await
+            }).await?;
+            if strict_validation {
+                check_range(&body, mode, padded)?;
+            }
+            Ok(body)
+        }).await
+    }
+
+    /// Get the API response headers and body bytes for a password range,
+    /// unless it was not modified since an earlier response
+    /// with the given `ETag` and `Last-Modified` headers.
+    ///
+    /// Returns `None` if the range was not modified.
+    /// Failed requests are retried, including the response body.
+    pub(crate) async fn range_bytes_if_modified(
+        &self,
+        prefix: Prefix,
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+    ) -> Result<Option<(HeaderMap, Bytes)>> {
+        let mut request = self.range_request(prefix, Mode::Sha1);
+        let validators = [(IF_NONE_MATCH, etag), (IF_MODIFIED_SINCE, last_modified)];
+        for (name, value) in validators {
+            if let Some(value) = value.and_then(|value| HeaderValue::from_str(value).ok()) {
+                request.headers.insert(name, value);
+            }
+        }
+        let response = self.retry.run(|| async {
+            let response = self.transport.get(request.clone()).await?;
+            if response.status == 304 {
+                return Ok(None)
+            }
+            let response = check_status(response)?;
+            let body = response.body.bytes().await?;
+            Ok(Some((response.headers, body)))
+        }).await?;
+        if let Some((_headers, body)) = &response {
+            if self.strict_validation {
+                check_range(body, Mode::Sha1, self.add_padding)?;
+            }
+        }
         Ok(response)
     }
 
     /// Get the API response body bytes for a password range.
     ///
     /// Use this method if you want to parse the response body yourself.
-    pub async fn range_bytes(&self, prefix: Prefix) -> reqwest::Result<Bytes> {
-        let response = self.range_response(prefix).await?;
-        let body = response.bytes().await?;
-        Ok(body)
+    pub async fn range_bytes(&self, prefix: Prefix) -> Result<Bytes> {
+        self.shared_range_bytes(prefix, Mode::Sha1).await
     }
 
     /// Get the API response body text for a password range.
     ///
     /// Use this method if you want to parse the response body yourself.
-    pub async fn range_text(&self, prefix: Prefix) -> reqwest::Result<String> {
-        let response = self.range_response(prefix).await?;
-        let body = response.text().await?;
-        Ok(body)
+    /// Invalid UTF-8 sequences are replaced.
+    pub async fn range_text(&self, prefix: Prefix) -> Result<String> {
+        let body = self.shared_range_bytes(prefix, Mode::Sha1).await?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
     }
 
     /// Get the API response for a password range,
@@ -165,7 +663,7 @@ impl Api {
     /// corresponding breach counts.
     ///
     /// Lines that cannot be parsed yield `Err(Bytes)`.
-    pub async fn range_raw(&self, prefix: Prefix) -> reqwest::Result<impl Iterator<Item=Result<(Suffix, u32), Bytes>>> {
+    pub async fn range_raw(&self, prefix: Prefix) -> Result<impl Iterator<Item=Result<(Suffix, u32), Bytes>>> {
         let body = self.range_bytes(prefix).await?;
         Ok(RangeIter::new(body))
     }
@@ -175,91 +673,341 @@ impl Api {
     /// corresponding breach counts.
     ///
     /// Lines that cannot be parsed are omitted.
-    pub async fn range(&self, prefix: Prefix) -> reqwest::Result<impl Iterator<Item=(Suffix, u32)>> {
+    /// This buffers the whole response,
+    /// use [`range_stream`](Self::range_stream) to avoid that.
+    pub async fn range(&self, prefix: Prefix) -> Result<impl Iterator<Item=(Suffix, u32)>> {
         let body = self.range_bytes(prefix).await?;
         Ok(RangeIter::new(body).filter_map(|result| result.ok()))
     }
 
+    /// Get the API response for a password range,
+    /// and parse it while it is received into a stream of
+    /// password hash suffixes and corresponding breach counts.
+    ///
+    /// This does not buffer the whole response,
+    /// and stops reading it when the stream is dropped.
+    /// Lines that cannot be parsed are omitted.
+    pub async fn range_stream(&self, prefix: Prefix) -> Result<impl Stream<Item=Result<(Suffix, u32)>> + Send + Unpin> {
+        self.range_stream_mode(prefix, Mode::Sha1).await
+    }
+
+    async fn range_stream_mode<const N: usize>(
+        &self,
+        prefix: Prefix,
+        mode: Mode,
+    ) -> Result<RangeStream<BodyStream, N>> {
+        if self.strict_validation {
+            // the whole response is needed to validate it
+            let body = self.shared_range_bytes(prefix, mode).await?;
+            return Ok(RangeStream::new(Body::from(body).into_stream()))
+        }
+        let response = self.range_response(prefix, mode).await?;
+        Ok(RangeStream::new(response.body.into_stream()))
+    }
+
+    /// Get the API response for a password range,
+    /// and check that it is well-formed.
+    ///
+    /// This checks the same as
+    /// [strict validation](ApiBuilder::strict_validation),
+    /// but returns the diagnostics instead of failing,
+    /// which helps to find out whether a proxy changes the responses.
+    pub async fn validate_range(&self, prefix: Prefix) -> Result<RangeDiagnostics> {
+        let response = self.range_response(prefix, Mode::Sha1).await?;
+        let body = response.body.bytes().await?;
+        Ok(validate_range(&body, Mode::Sha1, self.add_padding))
+    }
+
+    /// Find the breach count of a suffix in a password range.
+    ///
+    /// In constant-time mode the whole (shared) response is scanned,
+    /// otherwise the response is streamed until the first match.
+    async fn find_in_range<const N: usize>(
+        &self,
+        prefix: Prefix,
+        suffix: &Suffix<N>,
+        mode: Mode,
+    ) -> Result<u32> {
+        let count = if self.constant_time || self.strict_validation {
+            let body = self.shared_range_bytes(prefix, mode).await?;
+            let range = RangeIter::<N>::from_bytes(body).filter_map(|result| result.ok());
+            scan_range(suffix, range, true)
+        } else {
+            let range = self.range_stream_mode(prefix, mode).await?;
+            find_in_stream(suffix, range).await?
+        };
+        count.ok_or(Error::MalformedResponse)
+    }
+
     /// Count the number of known breaches for a password.
     ///
     /// This function ignores lines in the API response
-    /// that cannot be parsed.
-    pub async fn count_breaches(&self, password: &str) -> reqwest::Result<u32> {
-        let (prefix, suffix) = hash(password);
-        for (range_suffix, count) in self.range(prefix).await? {
-            if suffix == range_suffix {
-                return Ok(count)
-            }
-        }
-        Ok(0)
+    /// that cannot be parsed, but fails with [`Error::MalformedResponse`]
+    /// if none of them can.
+    pub async fn count_breaches<P: Password + ?Sized>(&self, password: &P) -> Result<u32> {
+        let hash = Hash::from(hash(password));
+        self.count_breaches_for_hash(&hash).await
     }
 
     /// Check if there exist known breaches for a password.
     ///
     /// This function ignores lines in the API response
-    /// that cannot be parsed.
-    pub async fn is_breached(&self, password: &str) -> reqwest::Result<bool> {
+    /// that cannot be parsed, but fails with [`Error::MalformedResponse`]
+    /// if none of them can.
+    pub async fn is_breached<P: Password + ?Sized>(&self, password: &P) -> Result<bool> {
         let count = self.count_breaches(password).await?;
         Ok(count > 0)
     }
+
+    /// Count the number of known breaches for a SHA-1 password hash.
+    ///
+    /// This gives the same result as [`count_breaches`](Self::count_breaches)
+    /// for the password, without needing the password itself.
+    /// Parse the hash from base16 (in any case) using [`str::parse`].
+    ///
+    /// This function ignores lines in the API response
+    /// that cannot be parsed, but fails with [`Error::MalformedResponse`]
+    /// if none of them can.
+    pub async fn count_breaches_for_hash(&self, hash: &Hash) -> Result<u32> {
+        self.find_in_range(hash.prefix(), hash.suffix(), Mode::Sha1).await
+    }
+
+    /// Check if there exist known breaches for a SHA-1 password hash.
+    ///
+    /// This gives the same result as [`is_breached`](Self::is_breached)
+    /// for the password, without needing the password itself.
+    /// Parse the hash from base16 (in any case) using [`str::parse`].
+    ///
+    /// This function ignores lines in the API response
+    /// that cannot be parsed, but fails with [`Error::MalformedResponse`]
+    /// if none of them can.
+    pub async fn is_hash_breached(&self, hash: &Hash) -> Result<bool> {
+        let count = self.count_breaches_for_hash(hash).await?;
+        Ok(count > 0)
+    }
+
+    /// Check a password, with an explicit result
+    /// when it is not known whether it has been breached.
+    pub async fn check<P: Password + ?Sized>(&self, password: &P) -> BreachStatus {
+        BreachStatus::from_count(self.count_breaches(password).await)
+    }
+
+    /// Check a SHA-1 password hash, with an explicit result
+    /// when it is not known whether it has been breached.
+    pub async fn check_hash(&self, hash: &Hash) -> BreachStatus {
+        BreachStatus::from_count(self.count_breaches_for_hash(hash).await)
+    }
+
+    /// Check if a password may be used,
+    /// which is when it has no known breaches.
+    ///
+    /// If the lookup fails, this is decided by the
+    /// [failure policy](ApiBuilder::failure_policy) of the instance.
+    pub async fn is_allowed<P: Password + ?Sized>(&self, password: &P) -> bool {
+        self.is_allowed_with(password, self.failure_policy).await
+    }
+
+    /// Check if a password may be used,
+    /// which is when it has no known breaches.
+    ///
+    /// If the lookup fails, this is decided by the given failure policy.
+    /// Passwords allowed after a failed lookup are reported
+    /// to the [fail-open hook](ApiBuilder::on_fail_open).
+    pub async fn is_allowed_with<P: Password + ?Sized>(
+        &self,
+        password: &P,
+        policy: FailurePolicy,
+    ) -> bool {
+        let status = self.check(password).await;
+        self.fail_open_hook.decide(&status, policy)
+    }
+
+    /// Get the API response body bytes for an NTLM password range.
+    ///
+    /// Use this method if you want to parse the response body yourself.
+    pub async fn range_ntlm_bytes(&self, prefix: Prefix) -> Result<Bytes> {
+        self.shared_range_bytes(prefix, Mode::Ntlm).await
+    }
+
+    /// Get the API response for an NTLM password range,
+    /// and parse it into an iterator of password hash suffixes and
+    /// corresponding breach counts.
+    ///
+    /// Lines that cannot be parsed are omitted.
+    pub async fn range_ntlm(&self, prefix: Prefix) -> Result<impl Iterator<Item=(NtlmSuffix, u32)>> {
+        let body = self.range_ntlm_bytes(prefix).await?;
+        Ok(RangeIter::from_bytes(body).filter_map(|result| result.ok()))
+    }
+
+    /// Count the number of known breaches for an NTLM password hash.
+    ///
+    /// This function ignores lines in the API response
+    /// that cannot be parsed, but fails with [`Error::MalformedResponse`]
+    /// if none of them can.
+    pub async fn count_breaches_for_ntlm_hash(&self, hash: &NtlmHash) -> Result<u32> {
+        self.find_in_range(hash.prefix(), hash.suffix(), Mode::Ntlm).await
+    }
+
+    /// Check if there exist known breaches for an NTLM password hash.
+    ///
+    /// This function ignores lines in the API response
+    /// that cannot be parsed, but fails with [`Error::MalformedResponse`]
+    /// if none of them can.
+    pub async fn is_ntlm_hash_breached(&self, hash: &NtlmHash) -> Result<bool> {
+        let count = self.count_breaches_for_ntlm_hash(hash).await?;
+        Ok(count > 0)
+    }
+}
+
+/// Get the URL for a password range.
+fn range_url(base_url: &Url, prefix: Prefix, mode: Mode) -> Url {
+    let path = format!("range/{}", prefix.as_str());
+    let mut url = base_url.join(&path).unwrap();
+    if mode == Mode::Ntlm {
+        url.set_query(Some("mode=ntlm"));
+    }
+    url
+}
+
+/// Fail with [`Error::InvalidRange`] if a range response body is not valid.
+fn check_range(body: &[u8], mode: Mode, padded: bool) -> Result<()> {
+    let diagnostics = validate_range(body, mode, padded);
+    if diagnostics.is_valid() {
+        Ok(())
+    } else {
+        Err(Error::InvalidRange(diagnostics))
+    }
+}
+
+/// Turn responses with a non-success status code into errors.
+fn check_status(response: Response) -> Result<Response> {
+    match status_error(response.status, &response.headers) {
+        Some(error) => Err(error),
+        None => Ok(response),
+    }
+}
+
+/// Get the error for a non-success response status code.
+fn status_error(status: u16, headers: &HeaderMap) -> Option<Error> {
+    if (200..300).contains(&status) {
+        None
+    } else {
+        let retry_after = headers.get(RETRY_AFTER)
+            .and_then(|value| error::parse_retry_after(value.as_bytes()));
+        Some(Error::from_status(status, retry_after))
+    }
+}
+
+/// Find the breach count of a suffix in a range.
+///
+/// In constant-time mode, every entry is compared and the count
+/// is selected without branching on the result of the comparisons.
+/// Otherwise, this returns at the first matching entry.
+///
+/// Returns `None` if the range is empty.
+fn scan_range<const N: usize>(
+    suffix: &Suffix<N>,
+    range: impl Iterator<Item=(Suffix<N>, u32)>,
+    constant_time: bool,
+) -> Option<u32> {
+    let mut is_empty = true;
+    let mut found = 0;
+    for (range_suffix, count) in range {
+        is_empty = false;
+        if constant_time {
+            found.conditional_assign(&count, suffix.ct_eq(&range_suffix));
+        } else if *suffix == range_suffix {
+            return Some(count)
+        }
+    }
+    if is_empty {
+        None
+    } else {
+        Some(found)
+    }
 }
 
 fn rstrip<'a>(bytes: &'a [u8], last: &[u8]) -> &'a [u8] {
     bytes.strip_suffix(last).unwrap_or(bytes)
 }
 
-fn parse_range_line(line: &[u8]) -> Option<(Suffix, u32)> {
-    if line.get(SUFFIX_SIZE) == Some(&LINE_SEPARATOR) {
-        let suffix = Suffix(line[..SUFFIX_SIZE].try_into().unwrap());
-        let count = std::str::from_utf8(&line[(SUFFIX_SIZE + 1)..]).ok()?.parse().ok()?;
+fn parse_range_line<const N: usize>(line: &[u8]) -> Option<(Suffix<N>, u32)> {
+    if line.get(N) == Some(&LINE_SEPARATOR) {
+        let suffix = Suffix(line[..N].try_into().unwrap());
+        let count = std::str::from_utf8(&line[(N + 1)..]).ok()?.parse().ok()?;
         Some((suffix, count))
     } else {
         None
     }
 }
 
-struct RangeIter {
+struct RangeIter<const N: usize = SUFFIX_SIZE> {
     bytes: Bytes,
     index: usize,
 }
 
 impl RangeIter {
     fn new(bytes: Bytes) -> Self {
+        Self::from_bytes(bytes)
+    }
+}
+
+impl<const N: usize> RangeIter<N> {
+    fn from_bytes(bytes: Bytes) -> Self {
         Self { bytes, index: 0 }
     }
 }
 
-impl Iterator for RangeIter {
-    type Item = Result<(Suffix, u32), Bytes>;
+impl<const N: usize> Iterator for RangeIter<N> {
+    type Item = Result<(Suffix<N>, u32), Bytes>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while self.index < self.bytes.len() {
-            let index = self.bytes.iter()
-                .skip(self.index)
-                .position(|byte| *byte == b'\n');
-            if let Some(index) = index {
-                let start = self.index;
-                let end = self.index + index;
-                let line = rstrip(&self.bytes[start..end], b"\r");
-                self.index = end + 1;  // step beyond the newline
-                let end = start + line.len();
-                if let Some(item) = parse_range_line(line) {
-                    return Some(Ok(item))
-                } else {
-                    return Some(Err(self.bytes.slice(start..end)))
-                }
-            } else {
-                self.index = self.bytes.len();
-                return None
-            }
+        if self.index >= self.bytes.len() {
+            return None
+        }
+        let start = self.index;
+        let end = self.bytes.iter()
+            .skip(start)
+            .position(|byte| *byte == b'\n')
+            .map_or(self.bytes.len(), |index| start + index);
+        let line = rstrip(&self.bytes[start..end], b"\r");
+        self.index = end + 1;  // step beyond the newline
+        let end = start + line.len();
+        if let Some(item) = parse_range_line(line) {
+            Some(Ok(item))
+        } else {
+            Some(Err(self.bytes.slice(start..end)))
         }
-        None
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Bytes, hash, RangeIter};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+
+    use super::{
+        Api, Bytes, DEFAULT_BASE_URL, Error, Hash, hash, hash_ntlm, HeaderMap, Mode, NtlmHash,
+        ParseHashError, Prefix, range_url, RangeIter, Request, Response, Result, scan_range,
+        Suffix, Transport, Url,
+    };
+    use crate::test_support::FixedRange;
+
+    /// Responds with a status code and `Retry-After` header.
+    #[derive(Debug)]
+    struct Status(u16, Option<&'static str>);
+
+    #[async_trait]
+    impl Transport for Status {
+        async fn get(&self, _request: Request) -> Result<Response> {
+            let mut headers = HeaderMap::new();
+            if let Some(retry_after) = self.1 {
+                headers.insert("retry-after", retry_after.parse().unwrap());
+            }
+            Ok(Response::new(self.0, headers, ""))
+        }
+    }
 
     #[test]
     fn test_hash() {
@@ -291,4 +1039,162 @@ mod tests {
             Ok(("2DE4C0087846D223DBBCCF071614590F300".to_string(), 0)),
         ]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_unterminated() {
+        let iter = RangeIter::new(Bytes::from_static(concat!(
+            "2D6980B9098804E7A83DC5831BFBAF3927F:1\r\n",
+            "2DC183F740EE76F27B78EB39C8AD972A757:52579",
+        ).as_bytes()));
+        let counts = iter
+            .map(|result| result.map(|(_suffix, count)| count))
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![Ok(1), Ok(52579)]);
+    }
+
+    #[test]
+    #[cfg(feature = "reqwest")]
+    fn test_base_url() {
+        let (prefix, _suffix) = hash("P@ssw0rd");
+        assert_eq!(
+            Api::new().range_url(prefix, Mode::Sha1).as_str(),
+            "https://api.pwnedpasswords.com/range/21BD1",
+        );
+        let api = Api::with_base_url("http://localhost:8080/hibp").unwrap();
+        assert_eq!(
+            api.range_url(prefix, Mode::Sha1).as_str(),
+            "http://localhost:8080/hibp/range/21BD1",
+        );
+        assert!(Api::with_base_url("localhost").is_err());
+        assert!(Api::with_base_url("ftp://localhost/").is_err());
+        assert!(Api::with_base_url("http://localhost/?key=1").is_err());
+    }
+
+    #[test]
+    fn test_hash_ntlm() {
+        let (prefix, suffix) = hash_ntlm("P@ssw0rd");
+        assert_eq!(&prefix.0, b"E19CC");
+        assert_eq!(&suffix.0, b"F75EE54E06B06A5907AF13CEF42");
+        let hash = "e19ccf75ee54e06b06a5907af13cef42".parse::<NtlmHash>();
+        assert_eq!(hash.unwrap().split(), (prefix, suffix));
+        assert!("e19ccf75ee54e06b06a5907af13cef4".parse::<NtlmHash>().is_err());
+        assert!("e19ccf75ee54e06b06a5907af13cef4x".parse::<NtlmHash>().is_err());
+        let base_url = Url::parse(DEFAULT_BASE_URL).unwrap();
+        assert_eq!(
+            range_url(&base_url, prefix, Mode::Ntlm).as_str(),
+            "https://api.pwnedpasswords.com/range/E19CC?mode=ntlm",
+        );
+    }
+
+    #[test]
+    fn test_prefix_suffix() {
+        let prefix = "21BD1".parse::<Prefix>().unwrap();
+        assert_eq!(prefix.to_string(), "21BD1");
+        assert_eq!(prefix.to_u32(), 0x21BD1);
+        assert_eq!(Prefix::from_u32(0x21BD1), Some(prefix));
+        assert_eq!(Prefix::from_u32(0).unwrap().as_str(), "00000");
+        assert_eq!(Prefix::from_u32(0xFFFFF).unwrap().as_str(), "FFFFF");
+        assert_eq!(Prefix::from_u32(Prefix::COUNT), None);
+        assert_eq!(
+            "21bd1".parse::<Prefix>(),
+            Err(ParseHashError::InvalidCharacter),
+        );
+        assert_eq!(
+            Prefix::try_from(&b"21BD"[..]),
+            Err(ParseHashError::InvalidLength { expected: 5, found: 4 }),
+        );
+
+        let chars = "2DC183F740EE76F27B78EB39C8AD972A757";
+        let suffix = chars.parse::<Suffix>().unwrap();
+        assert_eq!(suffix.to_string(), chars);
+        assert!(chars.to_lowercase().parse::<Suffix>().is_err());
+
+        let hash = Hash::from_parts(prefix, suffix);
+        assert_eq!(hash.to_string(), "21BD12DC183F740EE76F27B78EB39C8AD972A757");
+        assert_eq!(hash.clone().split(), super::hash("P@ssw0rd"));
+        assert_eq!(hash, "21bd12dc183f740ee76f27b78eb39c8ad972a757".parse().unwrap());
+    }
+
+    #[test]
+    fn test_hash_bytes() {
+        use std::ffi::{OsStr, OsString};
+
+        let expected = hash("P@ssw0rd");
+        assert_eq!(hash(b"P@ssw0rd"), expected);
+        assert_eq!(hash(&b"P@ssw0rd"[..]), expected);
+        assert_eq!(hash(&b"P@ssw0rd".to_vec()), expected);
+        assert_eq!(hash(&"P@ssw0rd".to_string()), expected);
+        assert_eq!(hash(OsStr::new("P@ssw0rd")), expected);
+        assert_eq!(hash(&OsString::from("P@ssw0rd")), expected);
+        assert_ne!(hash(&b"P@ssw0rd\xff"[..]), expected);
+    }
+
+    #[test]
+    fn test_count_constant_time() {
+        let range = (0..100u32)
+            .map(|index| (format!("{:035X}", index).parse().unwrap(), index + 1))
+            .collect::<Vec<(Suffix, u32)>>();
+        let comparisons = |position: u32, constant_time: bool| {
+            let suffix = format!("{:035X}", position).parse().unwrap();
+            let mut count = 0;
+            let entries = range.iter()
+                .cloned()
+                .inspect(|_| count += 1);
+            let breaches = scan_range(&suffix, entries, constant_time);
+            let expected = if position < 100 { position + 1 } else { 0 };
+            assert_eq!(breaches, Some(expected));
+            count
+        };
+        for position in [0, 50, 99, 1000] {
+            assert_eq!(comparisons(position, true), 100);
+        }
+        assert_eq!(comparisons(0, false), 1);
+        assert_eq!(comparisons(50, false), 51);
+        assert_eq!(scan_range(&range[0].0, std::iter::empty(), true), None);
+    }
+
+    #[test]
+    fn test_count_for_hash() {
+        let range = concat!(
+            "2D6980B9098804E7A83DC5831BFBAF3927F:1\r\n",
+            "2DC183F740EE76F27B78EB39C8AD972A757:52579\r\n",
+        );
+        let api = Api::builder().transport(FixedRange(range)).build().unwrap();
+        tokio_test::block_on(async {
+            let expected = api.count_breaches("P@ssw0rd").await.unwrap();
+            assert_eq!(expected, 52579);
+            for hex in [
+                "21BD12DC183F740EE76F27B78EB39C8AD972A757",
+                "21bd12dc183f740ee76f27b78eb39c8ad972a757",
+            ] {
+                let hash = hex.parse::<Hash>().unwrap();
+                assert_eq!(api.count_breaches_for_hash(&hash).await.unwrap(), expected);
+                assert!(api.is_hash_breached(&hash).await.unwrap());
+            }
+            let hash = Hash::from(hash("secret"));
+            assert_eq!(
+                api.is_hash_breached(&hash).await.unwrap(),
+                api.is_breached("secret").await.unwrap(),
+            );
+        });
+    }
+
+    #[test]
+    fn test_status() {
+        tokio_test::block_on(async {
+            let api = Api::builder().transport(Status(404, None)).build().unwrap();
+            let error = api.count_breaches("P@ssw0rd").await.unwrap_err();
+            assert!(matches!(error.inner(), Error::Status { status: 404, retry_after: None }));
+
+            let api = Api::builder().transport(Status(503, Some("5"))).build().unwrap();
+            let error = api.is_breached("P@ssw0rd").await.unwrap_err();
+            assert!(matches!(error.inner(), Error::Status { status: 503, .. }));
+            assert_eq!(error.retry_after(), Some(Duration::from_secs(5)));
+
+            let api = Api::builder().transport(Status(429, Some("3"))).build().unwrap();
+            let error = api.count_breaches("P@ssw0rd").await.unwrap_err();
+            assert!(matches!(error.inner(), Error::RateLimited { .. }));
+            assert_eq!(error.retry_after(), Some(Duration::from_secs(3)));
+        });
+    }
+}
@@ -0,0 +1,458 @@
+use std::fmt;
+use std::io::{BufRead, BufWriter, Seek, SeekFrom, Write};
+#[cfg(feature = "mmap")]
+use std::path::Path;
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+use super::{hash_bytes, parse_text, read_u32, read_u64};
+use crate::{Error, Hash, hash, HASH_SIZE, Password, Prefix, PREFIX_SIZE, Result, Suffix};
+
+/// the first bytes of a binary corpus file
+const MAGIC: &[u8; 8] = b"PASSLEAK";
+
+/// the current version of the binary corpus format
+const VERSION: u32 = 1;
+
+/// the size of the header in bytes
+const HEADER_SIZE: usize = 24;
+
+/// the size of the prefix index in bytes
+const INDEX_SIZE: usize = 8 * (Prefix::COUNT as usize + 1);
+
+/// the number of leading hash bytes that are implied by the prefix,
+/// the next byte is shared by the prefix and suffix so it is stored
+const IMPLIED_SIZE: usize = 2;
+
+/// the size of the stored part of a hash in bytes
+const KEY_SIZE: usize = HASH_SIZE / 2 - IMPLIED_SIZE;
+
+/// the size of an entry in bytes
+const ENTRY_SIZE: usize = KEY_SIZE + 4;
+
+/// The errors specific to the binary corpus and filter formats.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum CorpusError {
+    /// The file does not start with the format identifier.
+    InvalidMagic,
+    /// The file uses a version of the format that is not supported.
+    UnsupportedVersion(u32),
+    /// The file size does not match the number of entries in the header.
+    InvalidSize {
+        /// The expected size in bytes.
+        expected: u64,
+        /// The actual size in bytes.
+        found: u64,
+    },
+    /// The offsets in the prefix index are out of order or out of bounds.
+    CorruptIndex(Prefix),
+    /// A line of the text corpus cannot be parsed,
+    /// or an added hash is not base16, at the given line or entry.
+    MalformedLine(u64),
+    /// The hashes are not in ascending order, at the given entry.
+    Unsorted(u64),
+    /// The number of hash functions of a filter is zero or too large.
+    InvalidHashCount(u32),
+}
+
+impl fmt::Display for CorpusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CorpusError::InvalidMagic => write!(f, "not a binary corpus"),
+            CorpusError::UnsupportedVersion(version) => {
+                write!(f, "unsupported binary corpus version {}", version)
+            }
+            CorpusError::InvalidSize { expected, found } => write!(
+                f,
+                "binary corpus has {} bytes, expected {}",
+                found,
+                expected,
+            ),
+            CorpusError::CorruptIndex(prefix) => {
+                write!(f, "corrupt binary corpus index at prefix {}", prefix)
+            }
+            CorpusError::MalformedLine(line) => {
+                write!(f, "malformed text corpus line {}", line)
+            }
+            CorpusError::Unsorted(entry) => {
+                write!(f, "hashes are not in ascending order at entry {}", entry)
+            }
+            CorpusError::InvalidHashCount(hashes) => {
+                write!(f, "invalid filter hash function count {}", hashes)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CorpusError {}
+
+/// Get the stored part of a hash.
+///
+/// Returns `None` if the suffix is not valid base16.
+fn hash_key(prefix: Prefix, suffix: &Suffix) -> Option<[u8; KEY_SIZE]> {
+    let bytes = hash_bytes(prefix, suffix)?;
+    Some(bytes[IMPLIED_SIZE..].try_into().unwrap())
+}
+
+/// Get the suffix of a stored hash.
+fn key_suffix(prefix: Prefix, key: &[u8]) -> Suffix {
+    let value = prefix.to_u32();
+    let mut bytes = [0; HASH_SIZE / 2];
+    bytes[0] = (value >> 12) as u8;
+    bytes[1] = (value >> 4) as u8;
+    bytes[IMPLIED_SIZE..].copy_from_slice(key);
+    let mut chars = [0; HASH_SIZE];
+    base16ct::upper::encode(&bytes, &mut chars).unwrap();
+    Suffix(chars[PREFIX_SIZE..].try_into().unwrap())
+}
+
+/// A compact binary version of the database.
+///
+/// The format is versioned and uses little-endian integers:
+///
+///  * A 24 byte header: the bytes `PASSLEAK`, the format version (`u32`),
+///    four reserved zero bytes and the number of entries (`u64`).
+///  * The prefix index: for each of the [`Prefix::COUNT`] prefixes
+///    in ascending order, the number of entries before the prefix (`u64`),
+///    followed by the total number of entries.
+///  * The entries in ascending order of hash: the last 18 bytes
+///    of the binary SHA-1 hash followed by the breach count (`u32`).
+///    The first 2 bytes of the hash are implied by the prefix.
+///
+/// Use [`BinaryCorpusBuilder`] to convert the text corpus.
+///
+/// Lookups use a binary search within the entries of the prefix,
+/// which is **not** a constant-time operation.
+pub struct BinaryCorpus<B> {
+    bytes: B,
+}
+
+#[cfg(feature = "mmap")]
+impl BinaryCorpus<Mmap> {
+    /// Open and memory-map a binary corpus file.
+    ///
+    /// The file must not be modified while it is mapped.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the map is read-only and the file is documented
+        // to not be modified while it is mapped
+        #[allow(unsafe_code)]
+        let bytes = unsafe { Mmap::map(&file)? };
+        Self::from_bytes(bytes)
+    }
+}
+
+impl<B: AsRef<[u8]>> BinaryCorpus<B> {
+    /// Use the bytes of a binary corpus.
+    ///
+    /// This checks the header and the prefix index.
+    pub fn from_bytes(bytes: B) -> Result<Self> {
+        let data = bytes.as_ref();
+        if data.len() < HEADER_SIZE || &data[..MAGIC.len()] != MAGIC {
+            return Err(CorpusError::InvalidMagic.into())
+        }
+        let version = read_u32(data, 8);
+        if version != VERSION {
+            return Err(CorpusError::UnsupportedVersion(version).into())
+        }
+        let count = read_u64(data, 16);
+        let expected = count.checked_mul(ENTRY_SIZE as u64)
+            .and_then(|size| size.checked_add((HEADER_SIZE + INDEX_SIZE) as u64));
+        if expected != Some(data.len() as u64) {
+            return Err(CorpusError::InvalidSize {
+                expected: expected.unwrap_or(u64::MAX),
+                found: data.len() as u64,
+            }.into())
+        }
+        let corpus = Self { bytes };
+        let mut previous = 0;
+        for value in 0..=Prefix::COUNT {
+            let offset = corpus.index(value);
+            let is_first = value == 0;
+            let is_last = value == Prefix::COUNT;
+            if offset < previous
+                || offset > count
+                || (is_first && offset != 0)
+                || (is_last && offset != count)
+            {
+                let prefix = Prefix::from_u32(value.min(Prefix::COUNT - 1));
+                return Err(CorpusError::CorruptIndex(prefix.unwrap()).into())
+            }
+            previous = offset;
+        }
+        Ok(corpus)
+    }
+
+    /// Get the number of entries.
+    pub fn len(&self) -> u64 {
+        read_u64(self.bytes.as_ref(), 16)
+    }
+
+    /// Check if there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the prefix index value.
+    fn index(&self, value: u32) -> u64 {
+        read_u64(self.bytes.as_ref(), HEADER_SIZE + 8 * value as usize)
+    }
+
+    /// Get the entries of a prefix.
+    fn entries(&self, prefix: Prefix) -> &[u8] {
+        let value = prefix.to_u32();
+        let start = self.index(value) as usize;
+        let end = self.index(value + 1) as usize;
+        let offset = HEADER_SIZE + INDEX_SIZE;
+        &self.bytes.as_ref()[(offset + start * ENTRY_SIZE)..(offset + end * ENTRY_SIZE)]
+    }
+
+    /// Get the password hash suffixes and corresponding breach counts
+    /// for a password range.
+    ///
+    /// This yields the same items as [`Api::range`](crate::Api::range),
+    /// except for any padding entries.
+    pub fn range(
+        &self,
+        prefix: Prefix,
+    ) -> impl Iterator<Item=(Suffix, u32)> + '_ {
+        self.entries(prefix)
+            .chunks_exact(ENTRY_SIZE)
+            .map(move |entry| {
+                let suffix = key_suffix(prefix, &entry[..KEY_SIZE]);
+                (suffix, read_u32(entry, KEY_SIZE))
+            })
+    }
+
+    /// Count the number of known breaches for a password.
+    pub fn count_breaches<P: Password + ?Sized>(&self, password: &P) -> u32 {
+        self.count_breaches_for_hash(&Hash::from(hash(password)))
+    }
+
+    /// Check if there exist known breaches for a password.
+    pub fn is_breached<P: Password + ?Sized>(&self, password: &P) -> bool {
+        self.count_breaches(password) > 0
+    }
+
+    /// Count the number of known breaches for a SHA-1 password hash.
+    pub fn count_breaches_for_hash(&self, hash: &Hash) -> u32 {
+        let Some(key) = hash_key(hash.prefix(), hash.suffix()) else {
+            // hashes that are not base16 are never stored
+            return 0
+        };
+        let entries = self.entries(hash.prefix());
+        let mut low = 0;
+        let mut high = entries.len() / ENTRY_SIZE;
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let entry = &entries[(middle * ENTRY_SIZE)..((middle + 1) * ENTRY_SIZE)];
+            match entry[..KEY_SIZE].cmp(&key[..]) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return read_u32(entry, KEY_SIZE),
+            }
+        }
+        0
+    }
+
+    /// Check if there exist known breaches for a SHA-1 password hash.
+    pub fn is_hash_breached(&self, hash: &Hash) -> bool {
+        self.count_breaches_for_hash(hash) > 0
+    }
+}
+
+impl<B> fmt::Debug for BinaryCorpus<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BinaryCorpus").finish_non_exhaustive()
+    }
+}
+
+/// Writes a [`BinaryCorpus`].
+///
+/// Entries must be added in ascending order of hash,
+/// which is the order of the text corpus ordered by hash.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// use passleak::offline::BinaryCorpusBuilder;
+///
+/// # fn main() -> passleak::Result<()> {
+/// let text = File::open("pwned-passwords-sha1-ordered-by-hash.txt")?;
+/// let mut builder = BinaryCorpusBuilder::new(File::create("pwned.bin")?)?;
+/// builder.push_text(BufReader::new(text))?;
+/// builder.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct BinaryCorpusBuilder<W: Write + Seek> {
+    writer: BufWriter<W>,
+    /// the number of entries for each prefix
+    counts: Vec<u64>,
+    /// the prefix and stored part of the last hash
+    last: Option<(u32, [u8; KEY_SIZE])>,
+    len: u64,
+}
+
+impl<W: Write + Seek> BinaryCorpusBuilder<W> {
+    /// Start writing a binary corpus.
+    pub fn new(writer: W) -> Result<Self> {
+        let mut writer = BufWriter::new(writer);
+        // the header and index are written when finished
+        writer.write_all(&[0; HEADER_SIZE + INDEX_SIZE])?;
+        Ok(Self {
+            writer,
+            counts: vec![0; Prefix::COUNT as usize],
+            last: None,
+            len: 0,
+        })
+    }
+
+    /// Add a hash and its breach count.
+    ///
+    /// Fails with [`CorpusError::Unsorted`] if the hash
+    /// is not greater than the previous one.
+    pub fn push(&mut self, hash: &Hash, count: u32) -> Result<()> {
+        let key = hash_key(hash.prefix(), hash.suffix())
+            .ok_or(CorpusError::MalformedLine(self.len))?;
+        self.push_key(hash.prefix().to_u32(), key, count)
+    }
+
+    fn push_key(&mut self, prefix: u32, key: [u8; KEY_SIZE], count: u32) -> Result<()> {
+        if self.last.is_some_and(|last| last >= (prefix, key)) {
+            return Err(CorpusError::Unsorted(self.len).into())
+        }
+        self.writer.write_all(&key)?;
+        self.writer.write_all(&count.to_le_bytes())?;
+        self.counts[prefix as usize] += 1;
+        self.last = Some((prefix, key));
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Add all entries of the text corpus.
+    ///
+    /// Fails with [`CorpusError::MalformedLine`] for lines
+    /// that cannot be parsed, except for empty lines.
+    /// Returns the number of entries added.
+    pub fn push_text<R: BufRead>(&mut self, reader: R) -> Result<u64> {
+        let start = self.len;
+        parse_text(reader, |prefix, hash, count| {
+            let key = hash[IMPLIED_SIZE..].try_into().unwrap();
+            self.push_key(prefix.to_u32(), key, count)
+        })?;
+        Ok(self.len - start)
+    }
+
+    /// Write the header and prefix index, and get the writer.
+    pub fn finish(self) -> Result<W> {
+        let mut writer = self.writer.into_inner()
+            .map_err(|error| Error::Io(error.into_error()))?;
+        writer.seek(SeekFrom::Start(0))?;
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&[0; 4])?;
+        writer.write_all(&self.len.to_le_bytes())?;
+        let mut offset = 0u64;
+        for count in &self.counts {
+            writer.write_all(&offset.to_le_bytes())?;
+            offset += count;
+        }
+        writer.write_all(&offset.to_le_bytes())?;
+        let mut writer = writer.into_inner()
+            .map_err(|error| Error::Io(error.into_error()))?;
+        writer.seek(SeekFrom::End(0))?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write + Seek> fmt::Debug for BinaryCorpusBuilder<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BinaryCorpusBuilder")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{BinaryCorpus, BinaryCorpusBuilder, CorpusError, VERSION};
+    use crate::{Error, Hash, hash};
+
+    fn build(lines: &str) -> crate::Result<Vec<u8>> {
+        let mut builder = BinaryCorpusBuilder::new(Cursor::new(Vec::new()))?;
+        builder.push_text(lines.as_bytes())?;
+        Ok(builder.finish()?.into_inner())
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut hashes = (0..500)
+            .map(|index| {
+                let hash = Hash::from(hash(&format!("password{}", index)));
+                (hash.to_string(), index + 1)
+            })
+            .collect::<Vec<_>>();
+        hashes.sort();
+        let text = hashes.iter()
+            .map(|(hash, count)| format!("{}:{}\r\n", hash, count))
+            .collect::<String>();
+        let corpus = BinaryCorpus::from_bytes(build(&text).unwrap()).unwrap();
+        assert_eq!(corpus.len(), 500);
+        for index in 0..500 {
+            let password = format!("password{}", index);
+            assert_eq!(corpus.count_breaches(&password), index + 1);
+        }
+        assert!(!corpus.is_breached("not in the corpus"));
+        let (prefix, suffix) = hash("password7");
+        let range = corpus.range(prefix).collect::<Vec<_>>();
+        assert!(range.contains(&(suffix, 8)));
+    }
+
+    #[test]
+    fn test_errors() {
+        let unsorted = concat!(
+            "21BD12DC183F740EE76F27B78EB39C8AD972A757:1\n",
+            "00000000000000000000000000000000000000AA:2\n",
+        );
+        assert!(matches!(
+            build(unsorted),
+            Err(Error::Corpus(CorpusError::Unsorted(1))),
+        ));
+        assert!(matches!(
+            build("21BD12DC183F740EE76F27B78EB39C8AD972A757\n"),
+            Err(Error::Corpus(CorpusError::MalformedLine(1))),
+        ));
+
+        let bytes = build("21BD12DC183F740EE76F27B78EB39C8AD972A757:1\n").unwrap();
+        let mut invalid = bytes.clone();
+        invalid[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            BinaryCorpus::from_bytes(invalid),
+            Err(Error::Corpus(CorpusError::UnsupportedVersion(2))),
+        ));
+        let mut invalid = bytes.clone();
+        invalid.pop();
+        assert!(matches!(
+            BinaryCorpus::from_bytes(invalid),
+            Err(Error::Corpus(CorpusError::InvalidSize { .. })),
+        ));
+        let mut invalid = bytes.clone();
+        invalid[24] = 1;
+        assert!(matches!(
+            BinaryCorpus::from_bytes(invalid),
+            Err(Error::Corpus(CorpusError::CorruptIndex(_))),
+        ));
+        assert!(matches!(
+            BinaryCorpus::from_bytes(&bytes[1..]),
+            Err(Error::Corpus(CorpusError::InvalidMagic)),
+        ));
+    }
+}
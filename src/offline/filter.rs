@@ -0,0 +1,362 @@
+use std::f64::consts::LN_2;
+use std::fmt;
+use std::io::{BufRead, Write};
+#[cfg(feature = "mmap")]
+use std::path::Path;
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+use super::{CorpusError, hash_bytes, parse_text, read_u32, read_u64};
+use crate::{Hash, hash, HASH_SIZE, Password, Prefix, Result, Suffix};
+
+/// the first bytes of a filter file
+const MAGIC: &[u8; 8] = b"PLFILTER";
+
+/// the current version of the filter format
+const VERSION: u32 = 1;
+
+/// the size of the header in bytes
+const HEADER_SIZE: usize = 40;
+
+/// the maximum number of hash functions,
+/// above what the builder uses for any false positive rate
+const MAX_HASHES: u32 = 1024;
+
+/// Get the bit positions of a hash in a filter.
+///
+/// The hash is already uniformly distributed,
+/// so two parts of it are combined using double hashing.
+fn bit_positions(
+    bytes: &[u8; HASH_SIZE / 2],
+    hashes: u32,
+    bits: u64,
+) -> impl Iterator<Item=u64> {
+    let first = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+    let second = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) | 1;
+    (0..hashes as u64).map(move |index| {
+        first.wrapping_add(index.wrapping_mul(second)) % bits
+    })
+}
+
+/// A Bloom filter of breached password hashes.
+///
+/// This answers whether a password is breached
+/// using far less space than the corpus,
+/// at the cost of a [false positive rate](Self::false_positive_rate):
+/// a password that is not breached is sometimes reported as breached.
+/// Breached passwords are always reported as breached.
+/// The filter does not store breach counts.
+///
+/// The format is versioned and uses little-endian integers:
+///
+///  * A 40 byte header: the bytes `PLFILTER`, the format version (`u32`),
+///    the number of hash functions (`u32`), the number of bits (`u64`),
+///    the number of added hashes (`u64`), the minimum breach count (`u32`)
+///    and four reserved zero bytes.
+///  * The bits, in bytes from least to most significant bit.
+///
+/// Use [`FilterBuilder`] to create a filter.
+pub struct Filter<B> {
+    bytes: B,
+}
+
+#[cfg(feature = "mmap")]
+impl Filter<Mmap> {
+    /// Open and memory-map a filter file.
+    ///
+    /// The file must not be modified while it is mapped.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the map is read-only and the file is documented
+        // to not be modified while it is mapped
+        #[allow(unsafe_code)]
+        let bytes = unsafe { Mmap::map(&file)? };
+        Self::from_bytes(bytes)
+    }
+}
+
+impl<B: AsRef<[u8]>> Filter<B> {
+    /// Use the bytes of a filter file.
+    ///
+    /// This checks the header.
+    pub fn from_bytes(bytes: B) -> Result<Self> {
+        let data = bytes.as_ref();
+        if data.len() < HEADER_SIZE || &data[..MAGIC.len()] != MAGIC {
+            return Err(CorpusError::InvalidMagic.into())
+        }
+        let version = read_u32(data, 8);
+        if version != VERSION {
+            return Err(CorpusError::UnsupportedVersion(version).into())
+        }
+        let hashes = read_u32(data, 12);
+        if hashes == 0 || hashes > MAX_HASHES {
+            return Err(CorpusError::InvalidHashCount(hashes).into())
+        }
+        let bits = read_u64(data, 16);
+        let expected = HEADER_SIZE as u64 + bits.div_ceil(8);
+        if bits == 0 || expected != data.len() as u64 {
+            return Err(CorpusError::InvalidSize {
+                expected,
+                found: data.len() as u64,
+            }.into())
+        }
+        Ok(Self { bytes })
+    }
+
+    /// Get the bytes of the filter file.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.bytes.as_ref()
+    }
+
+    /// Write the filter file.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(self.as_bytes())?;
+        Ok(())
+    }
+
+    fn hashes(&self) -> u32 {
+        read_u32(self.as_bytes(), 12)
+    }
+
+    fn bits(&self) -> u64 {
+        read_u64(self.as_bytes(), 16)
+    }
+
+    /// Get the number of hashes added to the filter.
+    pub fn len(&self) -> u64 {
+        read_u64(self.as_bytes(), 24)
+    }
+
+    /// Check if no hashes were added to the filter.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the minimum breach count of the hashes in the filter.
+    ///
+    /// Passwords with fewer breaches are reported as not breached.
+    pub fn min_count(&self) -> u32 {
+        read_u32(self.as_bytes(), 32)
+    }
+
+    /// Get the expected rate of false positives,
+    /// for passwords that are not breached.
+    ///
+    /// This is `(1 - e^(-k * n / m))^k` for `k` hash functions,
+    /// `n` added hashes and `m` bits.
+    pub fn false_positive_rate(&self) -> f64 {
+        let hashes = self.hashes() as f64;
+        let fill = -hashes * self.len() as f64 / self.bits() as f64;
+        (1.0 - fill.exp()).powf(hashes)
+    }
+
+    /// Check if a password may be breached.
+    pub fn is_breached<P: Password + ?Sized>(&self, password: &P) -> bool {
+        self.is_hash_breached(&Hash::from(hash(password)))
+    }
+
+    /// Check if a SHA-1 password hash may be breached.
+    pub fn is_hash_breached(&self, hash: &Hash) -> bool {
+        let Some(hash) = hash_bytes(hash.prefix(), hash.suffix()) else {
+            // hashes that are not base16 are never added
+            return false
+        };
+        let bits = &self.as_bytes()[HEADER_SIZE..];
+        bit_positions(&hash, self.hashes(), self.bits()).all(|position| {
+            bits[(position / 8) as usize] & (1 << (position % 8)) != 0
+        })
+    }
+}
+
+impl<B: AsRef<[u8]>> fmt::Debug for Filter<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Filter")
+            .field("len", &self.len())
+            .field("bits", &self.bits())
+            .field("hashes", &self.hashes())
+            .field("min_count", &self.min_count())
+            .finish()
+    }
+}
+
+/// Creates a [`Filter`].
+///
+/// The filter is sized for an expected number of hashes
+/// and a target false positive rate.
+/// Adding more hashes than expected increases the false positive rate.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// use passleak::offline::FilterBuilder;
+///
+/// # fn main() -> passleak::Result<()> {
+/// let text = File::open("pwned-passwords-sha1-ordered-by-hash.txt")?;
+/// let mut builder = FilterBuilder::new(1_000_000_000, 0.01).min_count(10);
+/// builder.push_text(BufReader::new(text))?;
+/// builder.build().write_to(File::create("pwned.filter")?)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FilterBuilder {
+    bytes: Vec<u8>,
+    hashes: u32,
+    bits: u64,
+    len: u64,
+    min_count: u32,
+}
+
+impl FilterBuilder {
+    /// Create a filter builder.
+    ///
+    /// The filter uses about `1.44 * log2(1 / false_positive_rate)` bits
+    /// per expected hash, which is under 10 bits for a rate of 1%.
+    /// A rate that is not positive (or not a number)
+    /// is treated as the smallest positive rate.
+    pub fn new(expected_len: u64, false_positive_rate: f64) -> Self {
+        let rate = if false_positive_rate > 0.0 {
+            false_positive_rate.min(1.0)
+        } else {
+            f64::MIN_POSITIVE
+        };
+        let bits_per_hash = -rate.ln() / (LN_2 * LN_2);
+        let bits = ((expected_len as f64 * bits_per_hash).ceil() as u64).max(64);
+        let hashes = (bits_per_hash * LN_2).round().clamp(1.0, MAX_HASHES as f64) as u32;
+        let mut bytes = vec![0; HEADER_SIZE + bits.div_ceil(8) as usize];
+        bytes[..MAGIC.len()].copy_from_slice(MAGIC);
+        bytes[8..12].copy_from_slice(&VERSION.to_le_bytes());
+        bytes[12..16].copy_from_slice(&hashes.to_le_bytes());
+        bytes[16..24].copy_from_slice(&bits.to_le_bytes());
+        Self { bytes, hashes, bits, len: 0, min_count: 1 }
+    }
+
+    /// Set the minimum breach count of the hashes to add.
+    ///
+    /// This defaults to `1`, which excludes the padding entries
+    /// of range responses.
+    pub fn min_count(mut self, min_count: u32) -> Self {
+        self.min_count = min_count;
+        self
+    }
+
+    /// Add a SHA-1 password hash with its breach count.
+    ///
+    /// Hashes with less than the minimum breach count are skipped.
+    /// Returns whether the hash was added.
+    pub fn insert(&mut self, hash: &Hash, count: u32) -> bool {
+        self.insert_parts(hash.prefix(), hash.suffix(), count)
+    }
+
+    fn insert_parts(&mut self, prefix: Prefix, suffix: &Suffix, count: u32) -> bool {
+        match hash_bytes(prefix, suffix) {
+            Some(hash) => self.insert_bytes(&hash, count),
+            None => false,
+        }
+    }
+
+    fn insert_bytes(&mut self, hash: &[u8; HASH_SIZE / 2], count: u32) -> bool {
+        if count < self.min_count {
+            return false
+        }
+        let bits = &mut self.bytes[HEADER_SIZE..];
+        for position in bit_positions(hash, self.hashes, self.bits) {
+            bits[(position / 8) as usize] |= 1 << (position % 8);
+        }
+        self.len += 1;
+        true
+    }
+
+    /// Add the entries of a password range,
+    /// as returned by [`Api::range`](crate::Api::range).
+    ///
+    /// Returns the number of hashes added.
+    pub fn insert_range(
+        &mut self,
+        prefix: Prefix,
+        range: impl IntoIterator<Item=(Suffix, u32)>,
+    ) -> u64 {
+        range.into_iter()
+            .filter(|(suffix, count)| self.insert_parts(prefix, suffix, *count))
+            .count() as u64
+    }
+
+    /// Add the entries of the text corpus.
+    ///
+    /// Fails with [`CorpusError::MalformedLine`] for lines
+    /// that cannot be parsed, except for empty lines.
+    /// Returns the number of hashes added.
+    pub fn push_text<R: BufRead>(&mut self, reader: R) -> Result<u64> {
+        let start = self.len;
+        parse_text(reader, |_prefix, hash, count| {
+            self.insert_bytes(&hash, count);
+            Ok(())
+        })?;
+        Ok(self.len - start)
+    }
+
+    /// Create the filter.
+    pub fn build(mut self) -> Filter<Vec<u8>> {
+        self.bytes[24..32].copy_from_slice(&self.len.to_le_bytes());
+        self.bytes[32..36].copy_from_slice(&self.min_count.to_le_bytes());
+        Filter { bytes: self.bytes }
+    }
+}
+
+impl fmt::Debug for FilterBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FilterBuilder")
+            .field("len", &self.len)
+            .field("bits", &self.bits)
+            .field("hashes", &self.hashes)
+            .field("min_count", &self.min_count)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CorpusError, Filter, FilterBuilder, MAX_HASHES};
+    use crate::{Error, Hash, hash};
+
+    #[test]
+    fn test_filter() {
+        let mut text = (0..1000)
+            .map(|index| {
+                let hash = Hash::from(hash(&format!("password{}", index)));
+                format!("{}:{}\n", hash, index % 10)
+            })
+            .collect::<Vec<_>>();
+        text.sort();
+        let mut builder = FilterBuilder::new(1000, 0.01).min_count(5);
+        assert_eq!(builder.push_text(text.concat().as_bytes()).unwrap(), 500);
+        let filter = Filter::from_bytes(builder.build().as_bytes().to_vec());
+        let filter = filter.unwrap();
+        assert_eq!(filter.len(), 500);
+        assert_eq!(filter.min_count(), 5);
+        assert!(filter.false_positive_rate() < 0.01);
+        for index in (0..1000).filter(|index| index % 10 >= 5) {
+            assert!(filter.is_breached(&format!("password{}", index)));
+        }
+        let false_positives = (0..10000)
+            .filter(|index| filter.is_breached(&format!("other{}", index)))
+            .count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+        assert!(Filter::from_bytes(&filter.as_bytes()[1..]).is_err());
+        for hashes in [0, MAX_HASHES + 1] {
+            let mut bytes = filter.as_bytes().to_vec();
+            bytes[12..16].copy_from_slice(&hashes.to_le_bytes());
+            assert!(matches!(
+                Filter::from_bytes(bytes),
+                Err(Error::Corpus(CorpusError::InvalidHashCount(_))),
+            ));
+        }
+        for rate in [f64::NAN, -1.0, 0.0, f64::INFINITY] {
+            let builder = FilterBuilder::new(10, rate);
+            let filter = Filter::from_bytes(builder.build().as_bytes().to_vec()).unwrap();
+            assert!(!filter.is_breached("P@ssw0rd"));
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Lookups without network access, using a downloaded copy of the database.
+//!
+//! The database can be downloaded as a single text file named
+//! `pwned-passwords-sha1-ordered-by-hash`, using the official downloader.
+//!
+//! Lookups can use this [text file](TextCorpus) directly,
+//! or a [compact binary version](BinaryCorpus) of it.
+//! A much smaller [filter](Filter) can check if a password is breached,
+//! with a small rate of false positives.
+//!
+//! **Reference:**
+//! <https://github.com/HaveIBeenPwned/PwnedPasswordsDownloader>
+
+mod binary;
+mod filter;
+mod text;
+
+pub use binary::{BinaryCorpus, BinaryCorpusBuilder, CorpusError};
+pub use filter::{Filter, FilterBuilder};
+pub use text::TextCorpus;
+#[cfg(feature = "mmap")]
+pub use memmap2::Mmap;
+
+use std::io::BufRead;
+
+use crate::{
+    Error, HASH_SIZE, parse_range_line, Prefix, PREFIX_SIZE, Result, rstrip,
+    Suffix,
+};
+
+/// Get the binary SHA-1 hash.
+///
+/// Returns `None` if the suffix is not valid base16.
+fn hash_bytes(prefix: Prefix, suffix: &Suffix) -> Option<[u8; HASH_SIZE / 2]> {
+    let mut chars = [0; HASH_SIZE];
+    chars[..PREFIX_SIZE].copy_from_slice(&prefix.0);
+    chars[PREFIX_SIZE..].copy_from_slice(&suffix.0);
+    let mut bytes = [0; HASH_SIZE / 2];
+    base16ct::upper::decode(chars, &mut bytes).ok()?;
+    Some(bytes)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..(offset + 4)].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..(offset + 8)].try_into().unwrap())
+}
+
+/// Parse all entries of the text corpus.
+///
+/// Fails with [`CorpusError::MalformedLine`] for lines
+/// that cannot be parsed, except for empty lines.
+fn parse_text<R: BufRead>(
+    mut reader: R,
+    mut entry: impl FnMut(Prefix, [u8; HASH_SIZE / 2], u32) -> Result<()>,
+) -> Result<()> {
+    let mut line = Vec::new();
+    let mut number = 0;
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(())
+        }
+        number += 1;
+        let line = rstrip(rstrip(&line, b"\n"), b"\r");
+        if line.is_empty() {
+            continue
+        }
+        let malformed = || Error::from(CorpusError::MalformedLine(number));
+        let prefix = line.get(..PREFIX_SIZE)
+            .and_then(|prefix| Prefix::try_from(prefix).ok())
+            .ok_or_else(malformed)?;
+        let (suffix, count) = parse_range_line(&line[PREFIX_SIZE..])
+            .ok_or_else(malformed)?;
+        let hash = hash_bytes(prefix, &suffix).ok_or_else(malformed)?;
+        entry(prefix, hash, count)?;
+    }
+}
@@ -0,0 +1,209 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::{
+    Hash, hash, parse_range_line, Password, Prefix, PREFIX_SIZE, Result,
+    rstrip, scan_range, Suffix,
+};
+
+/// The text file with all password hashes ordered by hash.
+///
+/// Each line contains a SHA-1 hash and the breach count,
+/// separated by a colon (`HASH:COUNT`).
+///
+/// Lookups use a binary search over the file,
+/// so the file is never loaded into memory.
+/// The file must be ordered by hash, as downloaded.
+/// Lines that cannot be parsed are ignored.
+#[derive(Debug)]
+pub struct TextCorpus {
+    file: Mutex<BufReader<File>>,
+    len: u64,
+}
+
+impl TextCorpus {
+    /// Open the text file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_file(File::open(path)?)
+    }
+
+    /// Use an opened text file.
+    pub fn from_file(file: File) -> Result<Self> {
+        let len = file.metadata()?.len();
+        Ok(Self {
+            file: Mutex::new(BufReader::new(file)),
+            len,
+        })
+    }
+
+    /// Get the password hash suffixes and corresponding breach counts
+    /// for a password range.
+    ///
+    /// This yields the same items as [`Api::range`](crate::Api::range),
+    /// except for any padding entries.
+    pub fn range(
+        &self,
+        prefix: Prefix,
+    ) -> Result<impl Iterator<Item=(Suffix, u32)>> {
+        let mut file = self.file.lock().unwrap();
+        let mut reader = LineReader {
+            file: &mut file,
+            position: None,
+            line: Vec::new(),
+        };
+        let mut entries = Vec::new();
+        let mut start = self.lower_bound(&mut reader, prefix)?;
+        while let Some(end) = reader.read_line_at(start)? {
+            start = end;
+            let line = rstrip(&reader.line, b"\r");
+            if line.get(..PREFIX_SIZE) != Some(&prefix.0[..]) {
+                break
+            }
+            if let Some(entry) = parse_range_line(&line[PREFIX_SIZE..]) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries.into_iter())
+    }
+
+    /// Count the number of known breaches for a password.
+    pub fn count_breaches<P: Password + ?Sized>(
+        &self,
+        password: &P,
+    ) -> Result<u32> {
+        self.count_breaches_for_hash(&Hash::from(hash(password)))
+    }
+
+    /// Check if there exist known breaches for a password.
+    pub fn is_breached<P: Password + ?Sized>(
+        &self,
+        password: &P,
+    ) -> Result<bool> {
+        Ok(self.count_breaches(password)? > 0)
+    }
+
+    /// Count the number of known breaches for a SHA-1 password hash.
+    pub fn count_breaches_for_hash(&self, hash: &Hash) -> Result<u32> {
+        let range = self.range(hash.prefix())?;
+        Ok(scan_range(hash.suffix(), range, true).unwrap_or(0))
+    }
+
+    /// Check if there exist known breaches for a SHA-1 password hash.
+    pub fn is_hash_breached(&self, hash: &Hash) -> Result<bool> {
+        Ok(self.count_breaches_for_hash(hash)? > 0)
+    }
+
+    /// Find the start of the first line with a prefix
+    /// that is not less than the given one.
+    fn lower_bound(
+        &self,
+        reader: &mut LineReader<'_>,
+        prefix: Prefix,
+    ) -> Result<u64> {
+        // the first line that starts at or after an offset
+        // is ordered by that offset, so binary search over all offsets
+        let mut low = 0;
+        let mut high = self.len;
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let start = reader.skip_to_line(middle)?;
+            let is_after = match reader.read_line_at(start)? {
+                Some(_) => reader.line.get(..PREFIX_SIZE)
+                    .is_none_or(|line_prefix| line_prefix >= &prefix.0[..]),
+                None => true,
+            };
+            if is_after {
+                high = middle;
+            } else {
+                low = middle + 1;
+            }
+        }
+        reader.skip_to_line(low)
+    }
+}
+
+/// Reads lines at arbitrary offsets of a file.
+struct LineReader<'a> {
+    file: &'a mut BufReader<File>,
+    /// the offset of the file, if known
+    position: Option<u64>,
+    line: Vec<u8>,
+}
+
+impl LineReader<'_> {
+    /// Find the start of the first line that starts at or after an offset.
+    fn skip_to_line(&mut self, offset: u64) -> Result<u64> {
+        if offset == 0 {
+            return Ok(0)
+        }
+        // the line starts at the offset if the previous byte ends a line
+        self.file.seek(SeekFrom::Start(offset - 1))?;
+        self.line.clear();
+        let skipped = self.file.read_until(b'\n', &mut self.line)?;
+        let start = offset - 1 + skipped as u64;
+        self.position = Some(start);
+        Ok(start)
+    }
+
+    /// Read the line that starts at an offset, without the newline.
+    ///
+    /// Returns the start of the next line, or `None` at the end of the file.
+    fn read_line_at(&mut self, offset: u64) -> Result<Option<u64>> {
+        if self.position != Some(offset) {
+            self.file.seek(SeekFrom::Start(offset))?;
+        }
+        self.line.clear();
+        let read = self.file.read_until(b'\n', &mut self.line)?;
+        let end = offset + read as u64;
+        self.position = Some(end);
+        if read == 0 {
+            return Ok(None)
+        }
+        if self.line.last() == Some(&b'\n') {
+            self.line.pop();
+        }
+        Ok(Some(end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::TextCorpus;
+    use crate::{Hash, hash, Prefix};
+
+    #[test]
+    fn test_lookup() {
+        let mut hashes = (0..500)
+            .map(|index| {
+                let hash = Hash::from(hash(&format!("password{}", index)));
+                (hash.to_string(), index + 1)
+            })
+            .collect::<Vec<_>>();
+        hashes.sort();
+        let path = std::env::temp_dir()
+            .join(format!("passleak-text-{}.txt", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        for (hash, count) in &hashes {
+            write!(file, "{}:{}\r\n", hash, count).unwrap();
+        }
+        drop(file);
+
+        let corpus = TextCorpus::open(&path).unwrap();
+        for index in 0..500 {
+            let password = format!("password{}", index);
+            assert_eq!(corpus.count_breaches(&password).unwrap(), index + 1);
+        }
+        assert!(!corpus.is_breached("not in the corpus").unwrap());
+        let (first, _count) = &hashes[0];
+        let prefix = first[..5].parse::<Prefix>().unwrap();
+        let expected = hashes.iter()
+            .filter(|(hash, _count)| hash.starts_with(prefix.as_str()))
+            .count();
+        assert_eq!(corpus.range(prefix).unwrap().count(), expected);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
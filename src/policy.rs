@@ -0,0 +1,147 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::{Error, Result};
+
+/// The result of a password check,
+/// where a failed lookup is an explicit unknown result.
+#[derive(Debug)]
+pub enum BreachStatus {
+    /// The password has the number of known breaches.
+    Breached(u32),
+    /// The password has no known breaches.
+    NotBreached,
+    /// The lookup failed, so it is not known whether
+    /// the password has been breached.
+    Unknown(Error),
+}
+
+impl BreachStatus {
+    /// Get the status for the result of a breach count lookup.
+    pub(crate) fn from_count(count: Result<u32>) -> Self {
+        match count {
+            Ok(0) => BreachStatus::NotBreached,
+            Ok(count) => BreachStatus::Breached(count),
+            Err(error) => BreachStatus::Unknown(error),
+        }
+    }
+
+    /// Check if the password is known to be breached.
+    pub fn is_breached(&self) -> bool {
+        matches!(self, BreachStatus::Breached(_))
+    }
+
+    /// Check if the lookup failed.
+    pub fn is_unknown(&self) -> bool {
+        matches!(self, BreachStatus::Unknown(_))
+    }
+
+    /// Get the number of known breaches, if the lookup succeeded.
+    pub fn count(&self) -> Option<u32> {
+        match self {
+            BreachStatus::Breached(count) => Some(*count),
+            BreachStatus::NotBreached => Some(0),
+            BreachStatus::Unknown(_) => None,
+        }
+    }
+
+    /// Check if the password may be used,
+    /// deciding unknown results by the policy.
+    ///
+    /// This does not call the [fail-open hook](crate::ApiBuilder::on_fail_open),
+    /// so it is only used through [`FailOpenHook::decide`].
+    pub(crate) fn is_allowed(&self, policy: FailurePolicy) -> bool {
+        match self {
+            BreachStatus::Breached(_) => false,
+            BreachStatus::NotBreached => true,
+            BreachStatus::Unknown(_) => policy == FailurePolicy::FailOpen,
+        }
+    }
+}
+
+/// What to decide when it is not known whether
+/// a password has been breached, because the lookup failed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum FailurePolicy {
+    /// Allow the password, for example so that a registration
+    /// can proceed while the service is unavailable.
+    FailOpen,
+    /// Deny the password, for example to block
+    /// administrator password changes while the service is unavailable.
+    ///
+    /// This is the default.
+    #[default]
+    FailClosed,
+}
+
+/// A function called with the error of a failed lookup.
+type ErrorHook = dyn Fn(&Error) + Send + Sync;
+
+/// The function called for every fail-open decision.
+#[derive(Clone, Default)]
+pub(crate) struct FailOpenHook(Option<Arc<ErrorHook>>);
+
+impl FailOpenHook {
+    pub(crate) fn new(hook: impl Fn(&Error) + Send + Sync + 'static) -> Self {
+        Self(Some(Arc::new(hook)))
+    }
+
+    /// Decide whether a password may be used,
+    /// and call the hook if it is allowed despite an unknown result.
+    pub(crate) fn decide(&self, status: &BreachStatus, policy: FailurePolicy) -> bool {
+        let is_allowed = status.is_allowed(policy);
+        if let (true, BreachStatus::Unknown(error), Some(hook)) = (is_allowed, status, &self.0) {
+            hook(error);
+        }
+        is_allowed
+    }
+}
+
+impl fmt::Debug for FailOpenHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Some(FailOpenHook)"),
+            None => f.write_str("None"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use super::{BreachStatus, FailurePolicy};
+    use crate::test_support::Unreachable;
+    use crate::{Api, Error};
+
+    #[test]
+    fn test_policy() {
+        let failures = Arc::new(AtomicU32::new(0));
+        let hook_failures = failures.clone();
+        let api = Api::builder()
+            .transport(Unreachable)
+            .failure_policy(FailurePolicy::FailOpen)
+            .on_fail_open(move |error| {
+                assert!(matches!(error.inner(), Error::Transport(_)));
+                hook_failures.fetch_add(1, Ordering::Relaxed);
+            })
+            .build()
+            .unwrap();
+        tokio_test::block_on(async {
+            let status = api.check("P@ssw0rd").await;
+            assert!(status.is_unknown());
+            assert_eq!(status.count(), None);
+            assert!(api.is_allowed("P@ssw0rd").await);
+            assert!(!api.is_allowed_with("P@ssw0rd", FailurePolicy::FailClosed).await);
+        });
+        assert_eq!(failures.load(Ordering::Relaxed), 1);
+
+        let breached = BreachStatus::from_count(Ok(3));
+        assert!(breached.is_breached());
+        assert!(!breached.is_allowed(FailurePolicy::FailOpen));
+        let not_breached = BreachStatus::from_count(Ok(0));
+        assert_eq!(not_breached.count(), Some(0));
+        assert!(not_breached.is_allowed(FailurePolicy::FailClosed));
+    }
+}
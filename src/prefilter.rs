@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::offline::Filter;
+use crate::source::{self, RangeSource};
+use crate::{Api, Hash, hash, Password, Result};
+
+/// An [`Api`], or any other [`RangeSource`],
+/// with a local [`Filter`] in front of it.
+///
+/// Passwords that the filter reports as not breached are answered
+/// without a request, which saves a network round trip
+/// and does not disclose the password hash prefix.
+/// Only possible breaches are looked up to get the exact count.
+///
+/// If the filter was built with a [minimum breach
+/// count](crate::offline::FilterBuilder::min_count),
+/// passwords with fewer breaches are reported as not breached.
+pub struct PrefilteredApi<B, S = Api> {
+    api: S,
+    filter: Filter<B>,
+    checks: AtomicU64,
+    avoided: AtomicU64,
+}
+
+/// The statistics of a [`PrefilteredApi`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct PrefilterStats {
+    /// The number of lookups.
+    pub checks: u64,
+    /// The number of lookups answered by the filter, without a request.
+    pub avoided: u64,
+}
+
+impl PrefilterStats {
+    /// Get the number of lookups that needed a request.
+    pub fn forwarded(&self) -> u64 {
+        self.checks - self.avoided
+    }
+}
+
+impl<B, S> PrefilteredApi<B, S> {
+    /// Put a filter in front of the API.
+    pub fn new(api: S, filter: Filter<B>) -> Self {
+        Self {
+            api,
+            filter,
+            checks: AtomicU64::new(0),
+            avoided: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the API.
+    pub fn api(&self) -> &S {
+        &self.api
+    }
+
+    /// Get the filter.
+    pub fn filter(&self) -> &Filter<B> {
+        &self.filter
+    }
+
+    /// Get the statistics of the lookups so far.
+    pub fn stats(&self) -> PrefilterStats {
+        PrefilterStats {
+            checks: self.checks.load(Ordering::Relaxed),
+            avoided: self.avoided.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<B: AsRef<[u8]>, S: RangeSource> PrefilteredApi<B, S> {
+    /// Count the number of known breaches for a password.
+    ///
+    /// See [`Api::count_breaches`].
+    pub async fn count_breaches<P: Password + ?Sized>(&self, password: &P) -> Result<u32> {
+        let hash = Hash::from(hash(password));
+        self.count_breaches_for_hash(&hash).await
+    }
+
+    /// Check if there exist known breaches for a password.
+    ///
+    /// See [`Api::is_breached`].
+    pub async fn is_breached<P: Password + ?Sized>(&self, password: &P) -> Result<bool> {
+        let count = self.count_breaches(password).await?;
+        Ok(count > 0)
+    }
+
+    /// Count the number of known breaches for a SHA-1 password hash.
+    ///
+    /// See [`Api::count_breaches_for_hash`].
+    pub async fn count_breaches_for_hash(&self, hash: &Hash) -> Result<u32> {
+        self.checks.fetch_add(1, Ordering::Relaxed);
+        if self.filter.is_hash_breached(hash) {
+            source::count_breaches_for_hash(&self.api, hash).await
+        } else {
+            self.avoided.fetch_add(1, Ordering::Relaxed);
+            Ok(0)
+        }
+    }
+
+    /// Check if there exist known breaches for a SHA-1 password hash.
+    ///
+    /// See [`Api::is_hash_breached`].
+    pub async fn is_hash_breached(&self, hash: &Hash) -> Result<bool> {
+        let count = self.count_breaches_for_hash(hash).await?;
+        Ok(count > 0)
+    }
+}
+
+impl<B, S> std::fmt::Debug for PrefilteredApi<B, S>
+where
+    B: AsRef<[u8]>,
+    S: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PrefilteredApi")
+            .field("api", &self.api)
+            .field("filter", &self.filter)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PrefilteredApi, PrefilterStats};
+    use crate::offline::FilterBuilder;
+    use crate::{Api, Hash, hash};
+    use crate::test_support::Unreachable;
+
+    #[test]
+    fn test_prefilter() {
+        let mut builder = FilterBuilder::new(10, 0.0001);
+        builder.insert(&Hash::from(hash("P@ssw0rd")), 10);
+        let api = Api::builder().transport(Unreachable).build().unwrap();
+        let api = PrefilteredApi::new(api, builder.build());
+        tokio_test::block_on(async {
+            assert!(!api.is_breached("not breached").await.unwrap());
+            assert!(api.is_breached("P@ssw0rd").await.is_err());
+        });
+        assert_eq!(api.stats(), PrefilterStats { checks: 2, avoided: 1 });
+        assert_eq!(api.stats().forwarded(), 1);
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+use futures_timer::Delay;
+use futures_util::future::{Either, select};
+
+use crate::{Error, Result};
+
+/// When to retry failed requests.
+///
+/// Only [transient](Error::is_transient) failures are retried.
+/// The delay before each retry grows exponentially
+/// from the initial backoff up to the maximum backoff,
+/// and a random part of it is skipped (full jitter)
+/// so that many clients do not retry at the same time.
+/// When the service asks for a delay with the `Retry-After` header
+/// (with status codes 429 and 503), that delay is used instead,
+/// unless it is longer than the maximum backoff,
+/// in which case the request is not retried.
+///
+/// When the retries give up, the request fails with
+/// [`Error::RetriesExhausted`], which has the number of attempts.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "reqwest")] {
+/// use std::time::Duration;
+/// use passleak::{Api, RetryPolicy};
+///
+/// let retry = RetryPolicy::new()
+///     .max_attempts(4)
+///     .backoff(Duration::from_millis(50), Duration::from_secs(2))
+///     .deadline(Duration::from_secs(5));
+/// let api = Api::builder().retry(retry).build().unwrap();
+/// # }
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    deadline: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryPolicy {
+    /// Create a policy with 3 attempts, a backoff from
+    /// 100 milliseconds up to 5 seconds, jitter and no deadline.
+    pub fn new() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            jitter: true,
+            deadline: None,
+        }
+    }
+
+    /// Create a policy that never retries.
+    ///
+    /// This is the default of the [`Api`](crate::Api).
+    pub fn none() -> Self {
+        Self::new().max_attempts(1)
+    }
+
+    /// Set the maximum number of attempts, including the first one.
+    ///
+    /// A maximum of `0` is treated as `1`.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Set the delay before the first retry,
+    /// which doubles for every next retry up to the maximum.
+    ///
+    /// The maximum also limits the delay asked for by the service.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Set whether to wait a random part of the backoff.
+    ///
+    /// This is turned on (`true`) by default.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the maximum total time of all attempts.
+    ///
+    /// No retry is started when its delay would exceed the deadline,
+    /// and an attempt still running at the deadline is stopped,
+    /// which fails with [`Error::DeadlineExceeded`].
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Get the delay before the next attempt,
+    /// or `None` if the request should not be retried.
+    fn delay(&self, attempts: u32, error: &Error, elapsed: Duration) -> Option<Duration> {
+        if attempts >= self.max_attempts || !error.is_transient() {
+            return None
+        }
+        let retry_after = match error.status() {
+            Some(429 | 503) => error.retry_after(),
+            _ => None,
+        };
+        if retry_after.is_some_and(|retry_after| retry_after > self.max_backoff) {
+            return None
+        }
+        let delay = retry_after.unwrap_or_else(|| {
+            let factor = 2u32.saturating_pow(attempts - 1);
+            let backoff = self.initial_backoff
+                .saturating_mul(factor)
+                .min(self.max_backoff);
+            if self.jitter {
+                backoff.mul_f64(random_fraction())
+            } else {
+                backoff
+            }
+        });
+        match self.deadline {
+            Some(deadline) if elapsed + delay > deadline => None,
+            _ => Some(delay),
+        }
+    }
+
+    /// Get the time left until the deadline, if any.
+    fn time_left(&self, start: Instant) -> Option<Duration> {
+        self.deadline.map(|deadline| deadline.saturating_sub(start.elapsed()))
+    }
+
+    /// Send a request, and retry it according to the policy.
+    pub(crate) async fn run<F, T>(&self, mut request: impl FnMut() -> F) -> Result<T>
+    where
+        F: Future<Output=Result<T>>,
+    {
+        let start = Instant::now();
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let attempt = std::pin::pin!(request());
+            let result = match self.time_left(start) {
+                Some(left) => match select(attempt, Delay::new(left)).await {
+                    Either::Left((result, _delay)) => result,
+                    Either::Right(((), _attempt)) => {
+                        return Err(self.give_up(attempts, Error::DeadlineExceeded))
+                    }
+                },
+                None => attempt.await,
+            };
+            let error = match result {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            match self.delay(attempts, &error, start.elapsed()) {
+                Some(delay) => Delay::new(delay).await,
+                None => return Err(self.give_up(attempts, error)),
+            }
+        }
+    }
+
+    /// Send a request while blocking the current thread,
+    /// and retry it according to the policy.
+    ///
+    /// The request gets the time left until the deadline, if any,
+    /// to use as its timeout.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn run_blocking<T>(
+        &self,
+        mut request: impl FnMut(Option<Duration>) -> Result<T>,
+    ) -> Result<T> {
+        let start = Instant::now();
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let left = self.time_left(start);
+            if left == Some(Duration::ZERO) {
+                return Err(self.give_up(attempts, Error::DeadlineExceeded))
+            }
+            let error = match request(left) {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            match self.delay(attempts, &error, start.elapsed()) {
+                Some(delay) => std::thread::sleep(delay),
+                None => return Err(self.give_up(attempts, error)),
+            }
+        }
+    }
+
+    fn give_up(&self, attempts: u32, error: Error) -> Error {
+        if attempts > 1 {
+            Error::RetriesExhausted { attempts, error: Box::new(error) }
+        } else {
+            error
+        }
+    }
+}
+
+/// Get a random number in `[0, 1)`.
+fn random_fraction() -> f64 {
+    // the hasher is randomly seeded for every instance
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use async_trait::async_trait;
+    use futures_timer::Delay;
+
+    use super::RetryPolicy;
+    use crate::transport::{Request, Response, Transport};
+    use crate::{Api, Error, Result};
+
+    /// Responds with status 503 a number of times, then with a range.
+    #[derive(Debug, Default)]
+    struct Unavailable {
+        failures: u32,
+        requests: AtomicU32,
+    }
+
+    #[async_trait]
+    impl Transport for Unavailable {
+        async fn get(&self, _request: Request) -> Result<Response> {
+            if self.requests.fetch_add(1, Ordering::Relaxed) < self.failures {
+                Ok(Response::new(503, Default::default(), ""))
+            } else {
+                let body = "2DC183F740EE76F27B78EB39C8AD972A757:52579\r\n";
+                Ok(Response::new(200, Default::default(), body))
+            }
+        }
+    }
+
+    #[test]
+    fn test_retry() {
+        let retry = RetryPolicy::new()
+            .max_attempts(3)
+            .backoff(Duration::ZERO, Duration::ZERO);
+        for (failures, requests) in [(2, 3), (3, 3)] {
+            let transport = Arc::new(Unavailable { failures, ..Default::default() });
+            let api = Api::builder()
+                .transport(transport.clone())
+                .retry(retry.clone())
+                .build()
+                .unwrap();
+            let result = tokio_test::block_on(api.count_breaches("P@ssw0rd"));
+            assert_eq!(transport.requests.load(Ordering::Relaxed), requests);
+            match result.as_ref().map_err(Error::inner) {
+                Ok(count) => assert_eq!((failures, *count), (2, 52579)),
+                Err(Error::RetriesExhausted { attempts, error }) => {
+                    assert_eq!((failures, *attempts), (3, 3));
+                    assert_eq!(error.status(), Some(503));
+                }
+                Err(error) => panic!("unexpected error {:?}", error),
+            }
+            assert!(result.err().is_none_or(|error| error.is_transient()));
+        }
+    }
+
+    /// Never responds in time.
+    #[derive(Debug)]
+    struct Hanging;
+
+    #[async_trait]
+    impl Transport for Hanging {
+        async fn get(&self, _request: Request) -> Result<Response> {
+            Delay::new(Duration::from_secs(10)).await;
+            Err(Error::Transport("hanging".into()))
+        }
+    }
+
+    #[test]
+    fn test_deadline() {
+        let api = Api::builder()
+            .transport(Hanging)
+            .retry(RetryPolicy::new().deadline(Duration::from_millis(100)))
+            .build()
+            .unwrap();
+        let start = Instant::now();
+        let result = tokio_test::block_on(api.count_breaches("P@ssw0rd"));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        let error = result.unwrap_err();
+        assert!(matches!(error.inner(), Error::DeadlineExceeded));
+        assert!(error.is_transient());
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::new()
+            .max_attempts(4)
+            .backoff(Duration::from_millis(100), Duration::from_millis(300))
+            .jitter(false);
+        let unavailable = Error::from_status(503, None);
+        let delays = (1..=4)
+            .map(|attempts| policy.delay(attempts, &unavailable, Duration::ZERO))
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [
+            Some(Duration::from_millis(100)),
+            Some(Duration::from_millis(200)),
+            Some(Duration::from_millis(300)),
+            None,
+        ]);
+        let limited = Error::from_status(429, Some(Duration::from_secs(2)));
+        assert_eq!(policy.delay(1, &limited, Duration::ZERO), None);
+        let policy = policy.backoff(Duration::from_millis(100), Duration::from_secs(5));
+        assert_eq!(policy.delay(1, &limited, Duration::ZERO), Some(Duration::from_secs(2)));
+        let day = Error::from_status(503, Some(Duration::from_secs(86400)));
+        assert_eq!(RetryPolicy::new().delay(1, &day, Duration::ZERO), None);
+        let policy = policy.deadline(Duration::from_secs(1));
+        assert_eq!(policy.delay(1, &limited, Duration::ZERO), None);
+        assert_eq!(policy.delay(1, &unavailable, Duration::from_millis(950)), None);
+        assert_eq!(policy.delay(1, &Error::from_status(404, None), Duration::ZERO), None);
+        assert_eq!(policy.delay(1, &Error::MalformedResponse, Duration::ZERO), None);
+
+        let jittered = RetryPolicy::new().delay(1, &unavailable, Duration::ZERO);
+        assert!(jittered.unwrap() < Duration::from_millis(100));
+    }
+}
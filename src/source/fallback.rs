@@ -0,0 +1,319 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+use super::RangeSource;
+use crate::{Error, Hash, hash, Password, Prefix, Result, scan_range, Suffix};
+
+/// Which errors of a backend make a [`Fallback`] try the next backend.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum FallbackOn {
+    /// [Transient](Error::is_transient) errors,
+    /// including transient errors that exhausted the retries
+    /// or are [shared](Error::Shared) by concurrent lookups.
+    ///
+    /// This is the default.
+    #[default]
+    Transient,
+    /// Every error.
+    Any,
+    /// The errors for which the function returns `true`.
+    ///
+    /// The function gets the [inner](Error::inner) error,
+    /// without the [`Error::Shared`] wrapper.
+    Custom(fn(&Error) -> bool),
+}
+
+impl FallbackOn {
+    fn matches(&self, error: &Error) -> bool {
+        match self {
+            FallbackOn::Transient => error.is_transient(),
+            FallbackOn::Any => true,
+            FallbackOn::Custom(matches) => matches(error.inner()),
+        }
+    }
+}
+
+/// When to skip a backend that has been failing.
+#[derive(Clone, Copy, Debug)]
+struct HealthPolicy {
+    failures: u32,
+    cooldown: Duration,
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    /// the number of failures since the last success
+    failures: u32,
+    last_failure: Option<Instant>,
+}
+
+struct Backend {
+    name: String,
+    source: Box<dyn RangeSource>,
+    health: Mutex<Health>,
+}
+
+impl Backend {
+    fn is_healthy(&self, policy: Option<HealthPolicy>) -> bool {
+        let Some(policy) = policy else {
+            return true
+        };
+        let health = self.health.lock().unwrap();
+        health.failures < policy.failures
+            || health.last_failure.is_some_and(|failure| failure.elapsed() >= policy.cooldown)
+    }
+
+    fn record(&self, is_failure: bool) {
+        let mut health = self.health.lock().unwrap();
+        if is_failure {
+            health.failures = health.failures.saturating_add(1);
+            health.last_failure = Some(Instant::now());
+        } else {
+            *health = Health::default();
+        }
+    }
+}
+
+/// A [`RangeSource`] that tries a list of backends in order,
+/// such as the [online API](crate::Api), a mirror
+/// and an [offline corpus](crate::offline).
+///
+/// When a backend fails with an error matching [`FallbackOn`],
+/// the next backend is tried.
+/// Other errors are returned right away.
+/// If every backend fails, the error of the last one is returned.
+///
+/// With health tracking, backends that failed a number of times in a row
+/// are tried only after the healthy ones, until the cooldown has passed.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(feature = "reqwest")] {
+/// # tokio_test::block_on(async {
+/// use std::time::Duration;
+/// use passleak::Api;
+/// use passleak::offline::TextCorpus;
+/// use passleak::source::Fallback;
+///
+/// let mirror = Api::builder()
+///     .base_url("https://pwned.example.com/")
+///     .build()
+///     .unwrap();
+/// let sources = Fallback::new()
+///     .backend("public", Api::new())
+///     .backend("mirror", mirror)
+///     .backend("offline", TextCorpus::open("pwned-passwords-sha1.txt").unwrap())
+///     .health_tracking(3, Duration::from_secs(60));
+/// let (count, backend) = sources.count_breaches("secret").await.unwrap();
+/// println!("{} breaches according to {}", count, backend);
+/// # })
+/// # }
+/// ```
+#[derive(Default)]
+pub struct Fallback {
+    backends: Vec<Backend>,
+    fallback_on: FallbackOn,
+    health: Option<HealthPolicy>,
+}
+
+impl Fallback {
+    /// Create a fallback chain without backends.
+    ///
+    /// Health tracking is turned off by default.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a backend after the previous ones.
+    ///
+    /// The name is reported for the lookups answered by the backend.
+    pub fn backend(mut self, name: impl Into<String>, source: impl RangeSource + 'static) -> Self {
+        self.backends.push(Backend {
+            name: name.into(),
+            source: Box::new(source),
+            health: Mutex::default(),
+        });
+        self
+    }
+
+    /// Set which errors make the next backend be tried.
+    pub fn fallback_on(mut self, fallback_on: FallbackOn) -> Self {
+        self.fallback_on = fallback_on;
+        self
+    }
+
+    /// Skip a backend after a number of failures in a row,
+    /// until the cooldown has passed since its last failure.
+    ///
+    /// Only errors matching [`FallbackOn`] count as failures.
+    /// A skipped backend is still tried when every other backend fails.
+    pub fn health_tracking(mut self, failures: u32, cooldown: Duration) -> Self {
+        self.health = Some(HealthPolicy { failures: failures.max(1), cooldown });
+        self
+    }
+
+    /// Get the names of the backends, in order.
+    pub fn backends(&self) -> impl Iterator<Item=&str> {
+        self.backends.iter().map(|backend| backend.name.as_str())
+    }
+
+    /// Check if a backend is currently tried in order,
+    /// rather than skipped because it has been failing.
+    ///
+    /// Returns `None` if there is no backend with the name.
+    pub fn is_healthy(&self, name: &str) -> Option<bool> {
+        self.backends.iter()
+            .find(|backend| backend.name == name)
+            .map(|backend| backend.is_healthy(self.health))
+    }
+
+    /// Get the range of a prefix,
+    /// and the name of the backend that answered.
+    ///
+    /// Fails with [`Error::Config`] if there are no backends.
+    pub async fn fetch_range_with_backend(
+        &self,
+        prefix: Prefix,
+    ) -> Result<(Vec<(Suffix, u32)>, &str)> {
+        let (healthy, unhealthy): (Vec<_>, Vec<_>) = self.backends.iter()
+            .partition(|backend| backend.is_healthy(self.health));
+        let mut last_error = None;
+        for backend in healthy.into_iter().chain(unhealthy) {
+            match backend.source.fetch_range(prefix).await {
+                Ok(range) => {
+                    backend.record(false);
+                    return Ok((range, &backend.name))
+                }
+                Err(error) if self.fallback_on.matches(&error) => {
+                    backend.record(true);
+                    last_error = Some(error);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| Error::Config("no fallback backends were set".into())))
+    }
+
+    /// Count the number of known breaches for a password,
+    /// and get the name of the backend that answered.
+    ///
+    /// Every entry of the range is compared in constant time.
+    pub async fn count_breaches<P: Password + ?Sized>(&self, password: &P) -> Result<(u32, &str)> {
+        self.count_breaches_for_hash(&Hash::from(hash(password))).await
+    }
+
+    /// Count the number of known breaches for a SHA-1 password hash,
+    /// and get the name of the backend that answered.
+    ///
+    /// Every entry of the range is compared in constant time.
+    pub async fn count_breaches_for_hash(&self, hash: &Hash) -> Result<(u32, &str)> {
+        let (range, backend) = self.fetch_range_with_backend(hash.prefix()).await?;
+        let count = scan_range(hash.suffix(), range.into_iter(), true).unwrap_or(0);
+        Ok((count, backend))
+    }
+}
+
+#[async_trait]
+impl RangeSource for Fallback {
+    async fn fetch_range(&self, prefix: Prefix) -> Result<Vec<(Suffix, u32)>> {
+        let (range, _backend) = self.fetch_range_with_backend(prefix).await?;
+        Ok(range)
+    }
+}
+
+impl fmt::Debug for Fallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Fallback")
+            .field("backends", &self.backends().collect::<Vec<_>>())
+            .field("fallback_on", &self.fallback_on)
+            .field("health", &self.health)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use futures_timer::Delay;
+
+    use super::{Fallback, FallbackOn};
+    use crate::source::RangeSource;
+    use crate::test_support::{breached_corpus, Unreachable};
+    use crate::transport::{Request, Response, Transport};
+    use crate::{Api, Error, hash, Result, RetryPolicy};
+
+    /// Responds with status 503 after a short delay.
+    #[derive(Debug)]
+    struct Unavailable;
+
+    #[async_trait]
+    impl Transport for Unavailable {
+        async fn get(&self, _request: Request) -> Result<Response> {
+            Delay::new(Duration::from_millis(10)).await;
+            Ok(Response::new(503, Default::default(), ""))
+        }
+    }
+
+    #[test]
+    fn test_fallback() {
+        let corpus = breached_corpus();
+        let api = Api::builder().transport(Unreachable).build().unwrap();
+        let sources = Fallback::new()
+            .backend("public", api.clone())
+            .backend("offline", corpus)
+            .health_tracking(2, Duration::from_secs(60));
+        tokio_test::block_on(async {
+            for _ in 0..2 {
+                assert!(sources.is_healthy("public").unwrap());
+                let answer = sources.count_breaches("P@ssw0rd").await.unwrap();
+                assert_eq!(answer, (52579, "offline"));
+            }
+            assert!(!sources.is_healthy("public").unwrap());
+            assert!(sources.is_healthy("offline").unwrap());
+            assert_eq!(sources.count_breaches("secret").await.unwrap(), (0, "offline"));
+            assert_eq!(sources.is_healthy("mirror"), None);
+
+            let sources = Fallback::new()
+                .backend("public", api.clone())
+                .fallback_on(FallbackOn::Custom(|error| matches!(error, Error::Corpus(_))));
+            let result = sources.count_breaches("P@ssw0rd").await;
+            assert!(matches!(result.unwrap_err().inner(), Error::Transport(_)));
+            let sources = Fallback::new()
+                .backend("public", api)
+                .backend("offline", breached_corpus())
+                .fallback_on(FallbackOn::Custom(|error| matches!(error, Error::Transport(_))));
+            let answer = sources.count_breaches("P@ssw0rd").await.unwrap();
+            assert_eq!(answer, (52579, "offline"));
+            let result = Fallback::new().fetch_range(hash("P@ssw0rd").0).await;
+            assert!(matches!(result, Err(Error::Config(_))));
+        });
+    }
+
+    #[test]
+    fn test_concurrent_fallback() {
+        // concurrent lookups share the error of the retried request
+        let api = Api::builder()
+            .transport(Unavailable)
+            .retry(RetryPolicy::new().max_attempts(2).backoff(Duration::ZERO, Duration::ZERO))
+            .build()
+            .unwrap();
+        let sources = Fallback::new()
+            .backend("public", api)
+            .backend("offline", breached_corpus());
+        let (first, second) = tokio_test::block_on(futures_util::future::join(
+            sources.count_breaches("P@ssw0rd"),
+            sources.count_breaches("P@ssw0rd"),
+        ));
+        assert_eq!(first.unwrap(), (52579, "offline"));
+        assert_eq!(second.unwrap(), (52579, "offline"));
+        assert!(!FallbackOn::Transient.matches(&Error::Shared(std::sync::Arc::new(
+            Error::RetriesExhausted { attempts: 2, error: Box::new(Error::MalformedResponse) },
+        ))));
+    }
+}
@@ -0,0 +1,166 @@
+mod fallback;
+
+pub use fallback::{Fallback, FallbackOn};
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::offline::{BinaryCorpus, TextCorpus};
+use crate::{Api, Error, Hash, hash, Password, Prefix, Result, scan_range, Suffix};
+
+/// A source of password ranges,
+/// such as the [online API](Api) or an [offline corpus](crate::offline).
+///
+/// The functions in this module check passwords against any source,
+/// so the source can be swapped without changing the checks.
+///
+/// # Examples
+///
+/// ```
+/// use passleak::source::{self, RangeSource};
+///
+/// async fn check(source: &dyn RangeSource) -> passleak::Result<bool> {
+///     source::is_breached(source, "secret").await
+/// }
+/// ```
+#[async_trait]
+pub trait RangeSource: Send + Sync {
+    /// Get the password hash suffixes and corresponding breach counts
+    /// for a password range.
+    ///
+    /// Entries that cannot be parsed are omitted.
+    async fn fetch_range(&self, prefix: Prefix) -> Result<Vec<(Suffix, u32)>>;
+}
+
+#[async_trait]
+impl<S: RangeSource + ?Sized> RangeSource for &S {
+    async fn fetch_range(&self, prefix: Prefix) -> Result<Vec<(Suffix, u32)>> {
+        (**self).fetch_range(prefix).await
+    }
+}
+
+#[async_trait]
+impl<S: RangeSource + ?Sized> RangeSource for Box<S> {
+    async fn fetch_range(&self, prefix: Prefix) -> Result<Vec<(Suffix, u32)>> {
+        (**self).fetch_range(prefix).await
+    }
+}
+
+#[async_trait]
+impl<S: RangeSource + ?Sized> RangeSource for Arc<S> {
+    async fn fetch_range(&self, prefix: Prefix) -> Result<Vec<(Suffix, u32)>> {
+        (**self).fetch_range(prefix).await
+    }
+}
+
+/// Fails with [`Error::MalformedResponse`]
+/// if none of the lines in the API response can be parsed.
+#[async_trait]
+impl RangeSource for Api {
+    async fn fetch_range(&self, prefix: Prefix) -> Result<Vec<(Suffix, u32)>> {
+        let range = self.range(prefix).await?.collect::<Vec<_>>();
+        if range.is_empty() {
+            Err(Error::MalformedResponse)
+        } else {
+            Ok(range)
+        }
+    }
+}
+
+/// This reads the file while blocking the current thread.
+#[async_trait]
+impl RangeSource for TextCorpus {
+    async fn fetch_range(&self, prefix: Prefix) -> Result<Vec<(Suffix, u32)>> {
+        Ok(self.range(prefix)?.collect())
+    }
+}
+
+#[async_trait]
+impl<B: AsRef<[u8]> + Send + Sync> RangeSource for BinaryCorpus<B> {
+    async fn fetch_range(&self, prefix: Prefix) -> Result<Vec<(Suffix, u32)>> {
+        Ok(self.range(prefix).collect())
+    }
+}
+
+/// Count the number of known breaches for a password.
+///
+/// Every entry of the range is compared in constant time.
+pub async fn count_breaches<S, P>(source: &S, password: &P) -> Result<u32>
+where
+    S: RangeSource + ?Sized,
+    P: Password + ?Sized,
+{
+    count_breaches_for_hash(source, &Hash::from(hash(password))).await
+}
+
+/// Check if there exist known breaches for a password.
+///
+/// Every entry of the range is compared in constant time.
+pub async fn is_breached<S, P>(source: &S, password: &P) -> Result<bool>
+where
+    S: RangeSource + ?Sized,
+    P: Password + ?Sized,
+{
+    Ok(count_breaches(source, password).await? > 0)
+}
+
+/// Count the number of known breaches for a SHA-1 password hash.
+///
+/// Every entry of the range is compared in constant time.
+pub async fn count_breaches_for_hash<S>(source: &S, hash: &Hash) -> Result<u32>
+where
+    S: RangeSource + ?Sized,
+{
+    let range = source.fetch_range(hash.prefix()).await?;
+    Ok(scan_range(hash.suffix(), range.into_iter(), true).unwrap_or(0))
+}
+
+/// Check if there exist known breaches for a SHA-1 password hash.
+///
+/// Every entry of the range is compared in constant time.
+pub async fn is_hash_breached<S>(source: &S, hash: &Hash) -> Result<bool>
+where
+    S: RangeSource + ?Sized,
+{
+    Ok(count_breaches_for_hash(source, hash).await? > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::{count_breaches, is_breached, RangeSource};
+    use crate::offline::TextCorpus;
+    use crate::test_support::{breached_corpus, FixedRange};
+    use crate::{Api, Error, Hash, hash};
+
+    #[test]
+    fn test_sources() {
+        let corpus = breached_corpus();
+        let path = std::env::temp_dir()
+            .join(format!("passleak-sources-{}.txt", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        write!(file, "{}:52579\r\n", Hash::from(hash("P@ssw0rd"))).unwrap();
+        drop(file);
+        let text_corpus = TextCorpus::open(&path).unwrap();
+        let range = "2DC183F740EE76F27B78EB39C8AD972A757:52579\r\n";
+        let api = Api::builder().transport(FixedRange(range)).build().unwrap();
+        let sources: Vec<Box<dyn RangeSource>> = vec![
+            Box::new(corpus),
+            Box::new(text_corpus),
+            Box::new(api),
+        ];
+        tokio_test::block_on(async {
+            for source in &sources {
+                assert_eq!(count_breaches(source, "P@ssw0rd").await.unwrap(), 52579);
+                assert!(!is_breached(source, "P@ssw0rd1").await.unwrap());
+            }
+            // an API response without entries is an error, not a count of zero
+            let api = Api::builder().transport(FixedRange("")).build().unwrap();
+            let result = api.fetch_range(hash("P@ssw0rd").0).await;
+            assert!(matches!(result, Err(Error::MalformedResponse)));
+        });
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,136 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::{Bytes, BytesMut};
+use futures_util::stream::{Stream, StreamExt};
+
+use crate::{Error, parse_range_line, Result, rstrip, Suffix, SUFFIX_SIZE};
+
+/// Parses a range response while it is received.
+///
+/// Lines may be split across chunks of the response body.
+/// Lines that cannot be parsed are omitted.
+pub(crate) struct RangeStream<S, const N: usize = SUFFIX_SIZE> {
+    chunks: S,
+    buffer: BytesMut,
+    is_done: bool,
+}
+
+impl<S, const N: usize> RangeStream<S, N> {
+    pub(crate) fn new(chunks: S) -> Self {
+        Self {
+            chunks,
+            buffer: BytesMut::new(),
+            is_done: false,
+        }
+    }
+}
+
+impl<S, E, const N: usize> Stream for RangeStream<S, N>
+where
+    S: Stream<Item=Result<Bytes, E>> + Unpin,
+    E: Into<Error>,
+{
+    type Item = Result<(Suffix<N>, u32)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(index) = this.buffer.iter().position(|byte| *byte == b'\n') {
+                let line = this.buffer.split_to(index + 1);
+                if let Some(item) = parse_range_line(rstrip(&line[..index], b"\r")) {
+                    return Poll::Ready(Some(Ok(item)))
+                }
+                continue
+            }
+            if this.is_done {
+                // the last line may not end with a newline
+                let line = this.buffer.split();
+                let item = parse_range_line(rstrip(&line, b"\r"));
+                return Poll::Ready(item.map(Ok))
+            }
+            match this.chunks.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(chunk))) => this.buffer.extend_from_slice(&chunk),
+                Poll::Ready(Some(Err(error))) => {
+                    this.is_done = true;
+                    this.buffer.clear();
+                    return Poll::Ready(Some(Err(error.into())))
+                }
+                Poll::Ready(None) => this.is_done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Find the breach count of a suffix in a streamed range,
+/// and stop reading the range at the first matching entry.
+///
+/// This is not constant time.
+/// Returns `None` if the range is empty.
+pub(crate) async fn find_in_stream<S, const N: usize>(
+    suffix: &Suffix<N>,
+    mut range: S,
+) -> Result<Option<u32>>
+where
+    S: Stream<Item=Result<(Suffix<N>, u32)>> + Unpin,
+{
+    let mut is_empty = true;
+    while let Some(item) = range.next().await {
+        let (range_suffix, count) = item?;
+        is_empty = false;
+        if *suffix == range_suffix {
+            return Ok(Some(count))
+        }
+    }
+    Ok(if is_empty { None } else { Some(0) })
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use futures_util::stream::{self, StreamExt};
+
+    use super::{find_in_stream, RangeStream};
+    use crate::{Error, hash, Result};
+
+    fn chunks(text: &'static str, size: usize) -> Vec<Result<Bytes>> {
+        text.as_bytes()
+            .chunks(size)
+            .map(|chunk| Ok(Bytes::from_static(chunk)))
+            .collect()
+    }
+
+    #[test]
+    fn test_range_stream() {
+        let text = concat!(
+            "2D6980B9098804E7A83DC5831BFBAF3927F:1\r\n",
+            "xxx\r\n",
+            "2DC183F740EE76F27B78EB39C8AD972A757:52579\r\n",
+            "2DE4C0087846D223DBBCCF071614590F300:0",
+        );
+        let (_prefix, suffix) = hash("P@ssw0rd");
+        tokio_test::block_on(async {
+            for size in [1, 7, 40, text.len()] {
+                let range = RangeStream::<_>::new(stream::iter(chunks(text, size)));
+                let counts = range
+                    .map(|item| item.unwrap().1)
+                    .collect::<Vec<_>>()
+                    .await;
+                assert_eq!(counts, [1, 52579, 0], "chunks of {} bytes", size);
+            }
+
+            let range = RangeStream::new(stream::iter(chunks(text, 50)));
+            assert_eq!(find_in_stream(&suffix, range).await.unwrap(), Some(52579));
+            // the error after the entry is never read
+            let mut failing = chunks(text, 50);
+            failing[2] = Err(Error::MalformedResponse);
+            let range = RangeStream::new(stream::iter(failing));
+            assert_eq!(find_in_stream(&suffix, range).await.unwrap(), Some(52579));
+            let mut failing = chunks(text, 50);
+            failing[1] = Err(Error::MalformedResponse);
+            let range = RangeStream::new(stream::iter(failing));
+            assert!(find_in_stream(&suffix, range).await.is_err());
+        });
+    }
+}
@@ -0,0 +1,80 @@
+//! Transports, sources and servers shared by the tests.
+
+use std::io::Cursor;
+#[cfg(feature = "reqwest")]
+use std::io::{Read, Write};
+#[cfg(feature = "reqwest")]
+use std::net::TcpListener;
+#[cfg(feature = "reqwest")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "reqwest")]
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::offline::{BinaryCorpus, BinaryCorpusBuilder};
+use crate::transport::{Request, Response, Transport};
+use crate::{Error, Hash, hash, Result};
+
+/// A transport that fails every request.
+#[derive(Debug)]
+pub(crate) struct Unreachable;
+
+#[async_trait]
+impl Transport for Unreachable {
+    async fn get(&self, _request: Request) -> Result<Response> {
+        Err(Error::Transport("unreachable".into()))
+    }
+}
+
+/// A transport that answers every request with a fixed range.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct FixedRange(pub(crate) &'static str);
+
+#[async_trait]
+impl Transport for FixedRange {
+    async fn get(&self, _request: Request) -> Result<Response> {
+        Ok(Response::new(200, Default::default(), self.0))
+    }
+}
+
+/// A binary corpus that only contains "P@ssw0rd".
+pub(crate) fn breached_corpus() -> BinaryCorpus<Vec<u8>> {
+    let mut builder = BinaryCorpusBuilder::new(Cursor::new(Vec::new())).unwrap();
+    builder.push(&Hash::from(hash("P@ssw0rd")), 52579).unwrap();
+    let bytes = builder.finish().unwrap().into_inner();
+    BinaryCorpus::from_bytes(bytes).unwrap()
+}
+
+/// Start a local HTTP server.
+///
+/// It answers every request after the delay
+/// with a range that only contains "P@ssw0rd",
+/// and keeps the requests it received.
+/// Returns the base URL of the server.
+#[cfg(feature = "reqwest")]
+pub(crate) fn range_server(delay: Duration) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let server_requests = requests.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut buffer = [0; 1024];
+            let length = stream.read(&mut buffer).unwrap();
+            let request = String::from_utf8_lossy(&buffer[..length]).into_owned();
+            server_requests.lock().unwrap().push(request);
+            std::thread::sleep(delay);
+            let (_prefix, suffix) = hash("P@ssw0rd");
+            let body = format!("{}:52579\r\n", suffix);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
+                body.len(),
+                body,
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    (format!("http://{}/", address), requests)
+}
@@ -0,0 +1,360 @@
+//! Test support, with a fake in-memory service.
+//!
+//! A [`FakeTransport`] answers range requests from a set of
+//! password hashes and breach counts, without any network access.
+//! It plugs into the [`Api`] as its [`Transport`],
+//! so application code can be tested with the real API client.
+//! Failures can be injected to test error handling.
+//!
+//! This requires the `testing` cargo feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Arc;
+//! use passleak::testing::{Failure, FakeTransport};
+//!
+//! # tokio_test::block_on(async {
+//! let fake = Arc::new(FakeTransport::with_common_passwords());
+//! fake.insert("my-leaked-password", 3);
+//! let api = fake.api();
+//! assert_eq!(api.count_breaches("my-leaked-password").await.unwrap(), 3);
+//! assert!(!api.is_breached("correct horse battery staple").await.unwrap());
+//!
+//! fake.fail_next(Failure::Timeout);
+//! assert!(api.is_breached("my-leaked-password").await.is_err());
+//! assert_eq!(fake.requests(), 3);
+//! # })
+//! ```
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use http::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+use sha1::{Digest, Sha1};
+
+use crate::transport::{Request, Response, Transport};
+use crate::{
+    Api, Error, Hash, hash, hash_ntlm, Mode, NtlmHash, Password, Prefix,
+    Result,
+};
+
+/// Some of the most common breached passwords,
+/// with made-up breach counts of a realistic size.
+///
+/// These are used by [`FakeTransport::with_common_passwords`].
+pub const COMMON_PASSWORDS: &[(&str, u32)] = &[
+    ("123456", 37_359_195),
+    ("123456789", 16_629_796),
+    ("qwerty", 10_556_095),
+    ("password", 9_545_824),
+    ("12345678", 5_192_634),
+    ("111111", 4_846_878),
+    ("abc123", 2_877_918),
+    ("password1", 2_413_945),
+    ("iloveyou", 1_645_337),
+    ("P@ssw0rd", 52_579),
+];
+
+/// the number of padding entries in padded responses by default
+const DEFAULT_PADDING: usize = 100;
+
+/// A failure of a [`FakeTransport`] request.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum Failure {
+    /// The request times out,
+    /// which fails with [`Error::Transport`].
+    Timeout,
+    /// The service responds with a non-success status code,
+    /// which fails with [`Error::Status`] or [`Error::RateLimited`].
+    Status {
+        /// The HTTP status code.
+        status: u16,
+        /// The delay to send in the `Retry-After` header, if any.
+        retry_after: Option<Duration>,
+    },
+    /// The response has an unparseable line between the entries,
+    /// which is ignored by the API.
+    MalformedLine,
+    /// The response body has no parseable lines,
+    /// which fails with [`Error::MalformedResponse`].
+    MalformedBody,
+}
+
+/// The breach counts by hash suffix, in the order of the response.
+type Range = BTreeMap<String, u32>;
+
+/// A fake service with range responses of a set of
+/// password hashes and breach counts.
+///
+/// Responses are padded with random-looking entries
+/// with zero breaches when the API [adds
+/// padding](crate::ApiBuilder::add_padding), like the real service.
+/// Like the real service, a range is never empty:
+/// a range without other entries has a random-looking breached entry.
+/// Share the fake in an [`Arc`] to change it while it is used by an [`Api`].
+#[derive(Debug, Default)]
+pub struct FakeTransport {
+    ranges: Mutex<HashMap<(Prefix, Mode), Range>>,
+    failures: Mutex<Failures>,
+    padding: Option<usize>,
+    requests: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Failures {
+    next: VecDeque<Failure>,
+    prefixes: HashMap<Prefix, Failure>,
+}
+
+impl FakeTransport {
+    /// Create a fake service without breached passwords.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a fake service with the [common passwords](COMMON_PASSWORDS).
+    pub fn with_common_passwords() -> Self {
+        Self::from_passwords(COMMON_PASSWORDS.iter().copied())
+    }
+
+    /// Create a fake service with passwords and their breach counts.
+    ///
+    /// The passwords can be looked up by SHA-1 and NTLM hash.
+    pub fn from_passwords<'a>(passwords: impl IntoIterator<Item=(&'a str, u32)>) -> Self {
+        let fake = Self::new();
+        for (password, count) in passwords {
+            fake.insert(password, count);
+            fake.insert_ntlm(password, count);
+        }
+        fake
+    }
+
+    /// Create a fake service with SHA-1 password hashes
+    /// and their breach counts.
+    pub fn from_hashes(hashes: impl IntoIterator<Item=(Hash, u32)>) -> Self {
+        let fake = Self::new();
+        for (hash, count) in hashes {
+            fake.insert_hash(&hash, count);
+        }
+        fake
+    }
+
+    /// Set the number of padding entries in padded responses.
+    ///
+    /// This defaults to 100,
+    /// the real service pads responses to 800 to 1000 entries.
+    pub fn padding(mut self, padding: usize) -> Self {
+        self.padding = Some(padding);
+        self
+    }
+
+    /// Create an [`Api`] that uses this fake service.
+    pub fn api(self: &Arc<Self>) -> Api {
+        Api::builder()
+            .transport(self.clone())
+            .build()
+            .expect("default configuration is valid")
+    }
+
+    /// Add or replace the breach count of a password,
+    /// for lookups by SHA-1 hash.
+    pub fn insert<P: Password + ?Sized>(&self, password: &P, count: u32) {
+        self.insert_hash(&Hash::from(hash(password)), count);
+    }
+
+    /// Add or replace the breach count of a SHA-1 password hash.
+    pub fn insert_hash(&self, hash: &Hash, count: u32) {
+        self.insert_entry(hash.prefix(), Mode::Sha1, hash.suffix().as_str(), count);
+    }
+
+    /// Add or replace the breach count of a password,
+    /// for lookups by NTLM hash.
+    pub fn insert_ntlm(&self, password: &str, count: u32) {
+        self.insert_ntlm_hash(&NtlmHash::from(hash_ntlm(password)), count);
+    }
+
+    /// Add or replace the breach count of an NTLM password hash.
+    pub fn insert_ntlm_hash(&self, hash: &NtlmHash, count: u32) {
+        self.insert_entry(hash.prefix(), Mode::Ntlm, hash.suffix().as_str(), count);
+    }
+
+    fn insert_entry(&self, prefix: Prefix, mode: Mode, suffix: &str, count: u32) {
+        let mut ranges = self.ranges.lock().unwrap();
+        let range = ranges.entry((prefix, mode)).or_default();
+        range.insert(suffix.to_string(), count);
+    }
+
+    /// Fail the next request.
+    ///
+    /// Multiple failures fail the next requests in order.
+    pub fn fail_next(&self, failure: Failure) {
+        self.failures.lock().unwrap().next.push_back(failure);
+    }
+
+    /// Fail every request of a range,
+    /// until the failures are [cleared](Self::clear_failures).
+    pub fn fail_prefix(&self, prefix: Prefix, failure: Failure) {
+        self.failures.lock().unwrap().prefixes.insert(prefix, failure);
+    }
+
+    /// Remove all failures.
+    pub fn clear_failures(&self) {
+        let mut failures = self.failures.lock().unwrap();
+        failures.next.clear();
+        failures.prefixes.clear();
+    }
+
+    /// Get the number of requests received so far.
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Get the response body of a range.
+    fn range_body(&self, prefix: Prefix, mode: Mode, add_padding: bool) -> String {
+        let mut range = self.ranges.lock().unwrap()
+            .get(&(prefix, mode))
+            .cloned()
+            .unwrap_or_default();
+        let suffix_size = match mode {
+            Mode::Sha1 => crate::SUFFIX_SIZE,
+            Mode::Ntlm => crate::NTLM_SUFFIX_SIZE,
+        };
+        if add_padding {
+            let padding = self.padding.unwrap_or(DEFAULT_PADDING);
+            for index in 0..padding {
+                let suffix = padding_suffix(prefix, index, suffix_size);
+                range.entry(suffix).or_insert(0);
+            }
+        }
+        if range.is_empty() {
+            range.insert(padding_suffix(prefix, 0, suffix_size), 1);
+        }
+        range.iter()
+            .map(|(suffix, count)| format!("{}:{}\r\n", suffix, count))
+            .collect()
+    }
+}
+
+/// Get a random-looking suffix for a padding entry.
+fn padding_suffix(prefix: Prefix, index: usize, size: usize) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(prefix.as_str());
+    hasher.update(index.to_le_bytes());
+    let mut chars = [0; crate::HASH_SIZE];
+    base16ct::upper::encode(&hasher.finalize(), &mut chars).unwrap();
+    String::from_utf8_lossy(&chars[..size]).into_owned()
+}
+
+/// Parse the prefix and mode of a range request.
+fn parse_request(request: &Request) -> Option<(Prefix, Mode)> {
+    let prefix = request.url.path_segments()?.next_back()?.parse().ok()?;
+    let mode = match request.url.query() {
+        None => Mode::Sha1,
+        Some("mode=ntlm") => Mode::Ntlm,
+        Some(_) => return None,
+    };
+    Some((prefix, mode))
+}
+
+fn status_response(status: u16, retry_after: Option<Duration>) -> Response {
+    let mut headers = HeaderMap::new();
+    if let Some(retry_after) = retry_after {
+        headers.insert(RETRY_AFTER, HeaderValue::from(retry_after.as_secs()));
+    }
+    Response::new(status, headers, "")
+}
+
+#[async_trait]
+impl Transport for FakeTransport {
+    async fn get(&self, request: Request) -> Result<Response> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let Some((prefix, mode)) = parse_request(&request) else {
+            return Ok(status_response(400, None))
+        };
+        let failure = {
+            let mut failures = self.failures.lock().unwrap();
+            failures.next.pop_front()
+                .or_else(|| failures.prefixes.get(&prefix).cloned())
+        };
+        let add_padding = request.headers.get("add-padding")
+            .is_some_and(|value| value == "true");
+        let mut body = self.range_body(prefix, mode, add_padding);
+        match failure {
+            None => {}
+            Some(Failure::Timeout) => {
+                let error = io::Error::new(io::ErrorKind::TimedOut, "request timed out");
+                return Err(Error::Transport(Box::new(error)))
+            }
+            Some(Failure::Status { status, retry_after }) => {
+                return Ok(status_response(status, retry_after))
+            }
+            Some(Failure::MalformedLine) => {
+                let middle = body.match_indices("\r\n")
+                    .nth(body.lines().count() / 2)
+                    .map_or(0, |(index, _)| index + 2);
+                body.insert_str(middle, "not a range entry\r\n");
+            }
+            Some(Failure::MalformedBody) => {
+                body = "<html>service unavailable</html>\r\n".to_string();
+            }
+        }
+        Ok(Response::new(200, HeaderMap::new(), body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{Failure, FakeTransport};
+    use crate::{Api, Error, hash, NtlmHash, hash_ntlm};
+
+    #[test]
+    fn test_fake() {
+        let fake = Arc::new(FakeTransport::with_common_passwords().padding(20));
+        let api = fake.api();
+        let (prefix, _suffix) = hash("P@ssw0rd");
+        tokio_test::block_on(async {
+            assert_eq!(api.count_breaches("P@ssw0rd").await.unwrap(), 52579);
+            assert_eq!(api.range(prefix).await.unwrap().count(), 21);
+            let hash = NtlmHash::from(hash_ntlm("P@ssw0rd"));
+            assert_eq!(api.count_breaches_for_ntlm_hash(&hash).await.unwrap(), 52579);
+            let unpadded = Api::builder()
+                .transport(fake.clone())
+                .add_padding(false)
+                .build()
+                .unwrap();
+            assert_eq!(unpadded.range(prefix).await.unwrap().count(), 1);
+            // unpadded ranges without entries are not empty
+            assert!(!unpadded.is_breached("not breached").await.unwrap());
+
+            fake.fail_next(Failure::MalformedLine);
+            let raw = api.range_raw(prefix).await.unwrap();
+            assert_eq!(raw.filter(|result| result.is_err()).count(), 1);
+            fake.fail_next(Failure::MalformedBody);
+            assert!(matches!(
+                api.is_breached("P@ssw0rd").await,
+                Err(Error::MalformedResponse),
+            ));
+            fake.fail_prefix(prefix, Failure::Status {
+                status: 429,
+                retry_after: Some(Duration::from_secs(3)),
+            });
+            for _ in 0..2 {
+                let error = api.is_breached("P@ssw0rd").await.unwrap_err();
+                assert!(matches!(error.inner(), Error::RateLimited { .. }));
+                assert_eq!(error.retry_after(), Some(Duration::from_secs(3)));
+            }
+            fake.clear_failures();
+            assert!(api.is_breached("P@ssw0rd").await.unwrap());
+        });
+        assert_eq!(fake.requests(), 10);
+    }
+}
@@ -0,0 +1,272 @@
+//! The HTTP transport of the [`Api`](crate::Api).
+//!
+//! The API only needs to send `GET` requests and receive the responses,
+//! which is abstracted by the [`Transport`] trait.
+//! The default transport uses [`reqwest`],
+//! which requires the `reqwest` cargo feature (enabled by default).
+//! Implement the trait to use a different HTTP client or async runtime,
+//! and set it with [`ApiBuilder::transport`](crate::ApiBuilder::transport).
+//!
+//! # Examples
+//!
+//! ```
+//! use async_trait::async_trait;
+//! use passleak::transport::{Request, Response, Transport};
+//!
+//! #[derive(Debug)]
+//! struct Offline;
+//!
+//! #[async_trait]
+//! impl Transport for Offline {
+//!     async fn get(&self, request: Request) -> passleak::Result<Response> {
+//!         // a range that only contains "P@ssw0rd"
+//!         let body = "2DC183F740EE76F27B78EB39C8AD972A757:52579\r\n";
+//!         Ok(Response::new(200, Default::default(), body))
+//!     }
+//! }
+//!
+//! # tokio_test::block_on(async {
+//! let api = passleak::Api::builder().transport(Offline).build().unwrap();
+//! assert_eq!(api.count_breaches("P@ssw0rd").await.unwrap(), 52579);
+//! # })
+//! ```
+
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures_util::stream::{self, Stream, StreamExt};
+pub use http::HeaderMap;
+pub use url::Url;
+
+use crate::Result;
+
+/// A `GET` request sent by the [`Api`](crate::Api).
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Request {
+    /// The URL, including the query.
+    pub url: Url,
+    /// The headers to send, including the `User-Agent`.
+    pub headers: HeaderMap,
+    /// The timeout from sending the request
+    /// until the response body is received, if any.
+    pub timeout: Option<Duration>,
+}
+
+/// The response to a [`Request`].
+///
+/// Non-success status codes are turned into errors by the API,
+/// so transports should return them as responses.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Response {
+    /// The HTTP status code.
+    pub status: u16,
+    /// The response headers.
+    pub headers: HeaderMap,
+    /// The response body.
+    pub body: Body,
+}
+
+impl Response {
+    /// Create a response.
+    pub fn new(status: u16, headers: HeaderMap, body: impl Into<Body>) -> Self {
+        Self { status, headers, body: body.into() }
+    }
+}
+
+/// The chunks of a response body, as they are received.
+pub type BodyStream = Pin<Box<dyn Stream<Item=Result<Bytes>> + Send>>;
+
+/// The body of a [`Response`],
+/// either complete or as a stream of chunks.
+pub struct Body {
+    stream: BodyStream,
+}
+
+impl Body {
+    /// Create a body from a stream of chunks,
+    /// which is read as the response is received.
+    pub fn from_stream<S>(stream: S) -> Self
+    where
+        S: Stream<Item=Result<Bytes>> + Send + 'static,
+    {
+        Self { stream: Box::pin(stream) }
+    }
+
+    /// Receive the whole body.
+    pub async fn bytes(mut self) -> Result<Bytes> {
+        let mut bytes = BytesMut::new();
+        while let Some(chunk) = self.stream.next().await {
+            bytes.extend_from_slice(&chunk?);
+        }
+        Ok(bytes.freeze())
+    }
+
+    /// Get the stream of chunks of the body.
+    pub fn into_stream(self) -> BodyStream {
+        self.stream
+    }
+}
+
+impl From<Bytes> for Body {
+    fn from(bytes: Bytes) -> Self {
+        Self::from_stream(stream::once(async { Ok(bytes) }))
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::from(Bytes::from(bytes))
+    }
+}
+
+impl From<String> for Body {
+    fn from(text: String) -> Self {
+        Self::from(Bytes::from(text))
+    }
+}
+
+impl From<&'static str> for Body {
+    fn from(text: &'static str) -> Self {
+        Self::from(Bytes::from_static(text.as_bytes()))
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Body").finish_non_exhaustive()
+    }
+}
+
+/// Sends the requests of the [`Api`](crate::Api).
+///
+/// Implementations report failures to send a request or receive
+/// a response as errors, such as [`Error::Transport`](crate::Error::Transport).
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Send a `GET` request and receive the response.
+    ///
+    /// The body may still be received while it is read.
+    async fn get(&self, request: Request) -> Result<Response>;
+}
+
+#[async_trait]
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    async fn get(&self, request: Request) -> Result<Response> {
+        (**self).get(request).await
+    }
+}
+
+#[async_trait]
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    async fn get(&self, request: Request) -> Result<Response> {
+        (**self).get(request).await
+    }
+}
+
+impl fmt::Debug for dyn Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Transport")
+    }
+}
+
+/// The default [`Transport`], using a [`reqwest::Client`].
+///
+/// This requires the `reqwest` cargo feature (enabled by default).
+#[cfg(feature = "reqwest")]
+#[derive(Clone, Debug, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "reqwest")]
+impl ReqwestTransport {
+    /// Use a client.
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    /// Get the client.
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+}
+
+#[cfg(feature = "reqwest")]
+#[async_trait]
+impl Transport for ReqwestTransport {
+    async fn get(&self, request: Request) -> Result<Response> {
+        let mut builder = self.client.get(request.url).headers(request.headers);
+        if let Some(timeout) = request.timeout {
+            builder = builder.timeout(timeout);
+        }
+        let response = builder.send().await?;
+        let status = response.status().as_u16();
+        let headers = response.headers().clone();
+        let body = response.bytes_stream()
+            .map(|chunk| chunk.map_err(Into::into));
+        Ok(Response::new(status, headers, Body::from_stream(body)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use futures_util::stream;
+
+    use super::{Body, Request, Response, Transport};
+    use crate::test_support::Unreachable;
+    use crate::{Api, Error, hash, Result};
+
+    /// Answers with a fixed range and keeps the last request.
+    #[derive(Debug, Default)]
+    struct Recording {
+        request: Mutex<Option<Request>>,
+    }
+
+    #[async_trait]
+    impl Transport for Recording {
+        async fn get(&self, request: Request) -> Result<Response> {
+            *self.request.lock().unwrap() = Some(request);
+            let chunks = ["2DC183F740EE76F2", "7B78EB39C8AD972A757:52579\r\n"]
+                .map(|chunk| Ok(Bytes::from_static(chunk.as_bytes())));
+            let body = Body::from_stream(stream::iter(chunks));
+            Ok(Response::new(200, Default::default(), body))
+        }
+    }
+
+    #[test]
+    fn test_transport() {
+        let transport = Arc::new(Recording::default());
+        let api = Api::builder()
+            .transport(transport.clone())
+            .user_agent("tests")
+            .build()
+            .unwrap();
+        tokio_test::block_on(async {
+            assert_eq!(api.count_breaches("P@ssw0rd").await.unwrap(), 52579);
+            let (prefix, _suffix) = hash("P@ssw0rd");
+            let text = api.range_text(prefix).await.unwrap();
+            assert!(text.starts_with("2DC183F740EE76F27B78EB39C8AD972A757:"));
+            let bytes = Body::from("text").bytes().await.unwrap();
+            assert_eq!(bytes, "text");
+        });
+        let request = transport.request.lock().unwrap().take().unwrap();
+        assert_eq!(request.url.as_str(), "https://api.pwnedpasswords.com/range/21BD1");
+        assert_eq!(request.headers["user-agent"], "tests");
+        assert_eq!(request.headers["add-padding"], "true");
+        assert!(request.timeout.is_none());
+
+        let api = Api::builder().transport(Unreachable).build().unwrap();
+        let result = tokio_test::block_on(api.is_breached("P@ssw0rd"));
+        assert!(matches!(result.unwrap_err().inner(), Error::Transport(_)));
+    }
+}
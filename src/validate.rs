@@ -0,0 +1,223 @@
+use std::fmt;
+
+use crate::{is_upper_hex, LINE_SEPARATOR, Mode, NTLM_SUFFIX_SIZE, rstrip, SUFFIX_SIZE};
+
+/// A problem found by validating a range response.
+///
+/// Lines are numbered from 1.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum RangeIssue {
+    /// The line is not a suffix and a breach count separated by a `:`.
+    Malformed {
+        /// The line number.
+        line: usize,
+    },
+    /// The suffix has characters other than uppercase base16.
+    InvalidSuffix {
+        /// The line number.
+        line: usize,
+    },
+    /// The suffix is not after the suffix of the previous entry.
+    Unsorted {
+        /// The line number.
+        line: usize,
+    },
+    /// The suffix is the same as the suffix of an earlier entry.
+    Duplicate {
+        /// The line number.
+        line: usize,
+    },
+    /// The response has no entries.
+    Empty,
+    /// Padding was requested, but the response has no
+    /// padding entries (with zero breaches).
+    MissingPadding,
+}
+
+impl fmt::Display for RangeIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RangeIssue::Malformed { line } => write!(f, "line {} is malformed", line),
+            RangeIssue::InvalidSuffix { line } => {
+                write!(f, "line {} has an invalid suffix", line)
+            }
+            RangeIssue::Unsorted { line } => write!(f, "line {} is not sorted", line),
+            RangeIssue::Duplicate { line } => write!(f, "line {} is a duplicate", line),
+            RangeIssue::Empty => write!(f, "no entries"),
+            RangeIssue::MissingPadding => write!(f, "no padding entries"),
+        }
+    }
+}
+
+/// The result of validating a range response.
+///
+/// See [`ApiBuilder::strict_validation`](crate::ApiBuilder::strict_validation).
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct RangeDiagnostics {
+    /// The number of entries, including padding entries.
+    pub entries: usize,
+    /// The number of padding entries (with zero breaches).
+    pub padding: usize,
+    /// The problems found, in the order of the lines.
+    pub issues: Vec<RangeIssue>,
+}
+
+impl RangeDiagnostics {
+    /// Check if no problems were found.
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for RangeDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} entries ({} padding)", self.entries, self.padding)?;
+        for (index, issue) in self.issues.iter().enumerate() {
+            let separator = if index == 0 { ": " } else { ", " };
+            write!(f, "{}{}", separator, issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Validate a range response body.
+///
+/// This does not compare the suffixes in constant time,
+/// which is not needed because they are all part of the response.
+pub(crate) fn validate_range(body: &[u8], mode: Mode, padded: bool) -> RangeDiagnostics {
+    match mode {
+        Mode::Sha1 => validate::<SUFFIX_SIZE>(body, padded),
+        Mode::Ntlm => validate::<NTLM_SUFFIX_SIZE>(body, padded),
+    }
+}
+
+fn validate<const N: usize>(body: &[u8], padded: bool) -> RangeDiagnostics {
+    let mut diagnostics = RangeDiagnostics::default();
+    if body.is_empty() {
+        diagnostics.issues.push(RangeIssue::Empty);
+        return diagnostics
+    }
+    let mut suffixes = Vec::new();
+    let body = body.strip_suffix(b"\n").unwrap_or(body);
+    for (index, line) in body.split(|byte| *byte == b'\n').enumerate() {
+        let line_number = index + 1;
+        let line = rstrip(line, b"\r");
+        let count = match line.get(N) {
+            Some(&LINE_SEPARATOR) => std::str::from_utf8(&line[(N + 1)..])
+                .ok()
+                .and_then(|count| count.parse::<u32>().ok()),
+            _ => None,
+        };
+        let Some(count) = count else {
+            diagnostics.issues.push(RangeIssue::Malformed { line: line_number });
+            continue
+        };
+        let suffix = &line[..N];
+        diagnostics.entries += 1;
+        if count == 0 {
+            diagnostics.padding += 1;
+        }
+        if !is_upper_hex(suffix) {
+            diagnostics.issues.push(RangeIssue::InvalidSuffix { line: line_number });
+        }
+        match suffixes.last() {
+            Some((previous, _)) if suffix < *previous => {
+                diagnostics.issues.push(RangeIssue::Unsorted { line: line_number });
+            }
+            _ => {}
+        }
+        suffixes.push((suffix, line_number));
+    }
+    // find duplicates, also when they are not next to each other
+    suffixes.sort();
+    for pair in suffixes.windows(2) {
+        if pair[0].0 == pair[1].0 {
+            diagnostics.issues.push(RangeIssue::Duplicate { line: pair[1].1 });
+        }
+    }
+    if diagnostics.entries == 0 {
+        diagnostics.issues.push(RangeIssue::Empty);
+    } else if padded && diagnostics.padding == 0 {
+        diagnostics.issues.push(RangeIssue::MissingPadding);
+    }
+    diagnostics.issues.sort_by_key(|issue| match issue {
+        RangeIssue::Malformed { line }
+        | RangeIssue::InvalidSuffix { line }
+        | RangeIssue::Unsorted { line }
+        | RangeIssue::Duplicate { line } => *line,
+        RangeIssue::Empty | RangeIssue::MissingPadding => usize::MAX,
+    });
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RangeIssue, validate_range};
+    use crate::test_support::FixedRange;
+    use crate::{Api, Error, hash, Mode};
+
+    #[test]
+    fn test_validate() {
+        let body = concat!(
+            "2D6980B9098804E7A83DC5831BFBAF3927F:1\r\n",
+            "2DC183F740EE76F27B78EB39C8AD972A757:52579\r\n",
+            "2DE4C0087846D223DBBCCF071614590F300:0\r\n",
+        );
+        let diagnostics = validate_range(body.as_bytes(), Mode::Sha1, true);
+        assert!(diagnostics.is_valid());
+        assert_eq!((diagnostics.entries, diagnostics.padding), (3, 1));
+
+        let body = concat!(
+            "2DC183F740EE76F27B78EB39C8AD972A757:52579\r\n",
+            "2D6980B9098804E7A83DC5831BFBAF3927F:1\r\n",
+            "2dea2b1d02714099e4b7a874b4364d518f6:2\r\n",
+            "xxx\r\n",
+            "2DC183F740EE76F27B78EB39C8AD972A757:52579",
+        );
+        let diagnostics = validate_range(body.as_bytes(), Mode::Sha1, true);
+        assert_eq!(diagnostics.entries, 4);
+        assert_eq!(diagnostics.issues, [
+            RangeIssue::Unsorted { line: 2 },
+            RangeIssue::InvalidSuffix { line: 3 },
+            RangeIssue::Malformed { line: 4 },
+            RangeIssue::Unsorted { line: 5 },
+            RangeIssue::Duplicate { line: 5 },
+            RangeIssue::MissingPadding,
+        ]);
+        assert_eq!(
+            diagnostics.to_string(),
+            "4 entries (0 padding): line 2 is not sorted, line 3 has an invalid suffix, \
+            line 4 is malformed, line 5 is not sorted, line 5 is a duplicate, no padding entries",
+        );
+        assert!(validate_range(body.as_bytes(), Mode::Ntlm, false).issues
+            .iter()
+            .all(|issue| matches!(issue, RangeIssue::Malformed { .. } | RangeIssue::Empty)));
+        assert_eq!(validate_range(b"", Mode::Sha1, false).issues, [RangeIssue::Empty]);
+
+        // a range without padding
+        let unpadded = FixedRange("2DC183F740EE76F27B78EB39C8AD972A757:52579\r\n");
+        let api = Api::builder().transport(unpadded).strict_validation(true).build().unwrap();
+        tokio_test::block_on(async {
+            match api.count_breaches("P@ssw0rd").await.as_ref().map_err(Error::inner) {
+                Err(Error::InvalidRange(diagnostics)) => {
+                    assert_eq!(diagnostics.issues, [RangeIssue::MissingPadding]);
+                }
+                result => panic!("unexpected result {:?}", result),
+            }
+            let result = api.range_text(hash("P@ssw0rd").0).await;
+            assert!(matches!(result.unwrap_err().inner(), Error::InvalidRange(_)));
+            let diagnostics = api.validate_range(hash("P@ssw0rd").0).await.unwrap();
+            assert_eq!(diagnostics.entries, 1);
+            assert!(!diagnostics.is_valid());
+        });
+        let api = Api::builder()
+            .transport(unpadded)
+            .add_padding(false)
+            .strict_validation(true)
+            .build()
+            .unwrap();
+        let count = tokio_test::block_on(api.count_breaches("P@ssw0rd")).unwrap();
+        assert_eq!(count, 52579);
+    }
+}